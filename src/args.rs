@@ -6,7 +6,7 @@ use {
         ArgAction,
     },
     std::{
-        collections::HashMap,
+        path::PathBuf,
         str::FromStr,
     },
 };
@@ -21,10 +21,8 @@ impl CallArgs {
     pub async fn validate(&self) -> Result<()> {
         match self.privileges {
             | Privilege::Normal => {
-                match &self.command {
-                    | Command::Direct(..) => Err(anyhow::anyhow!("experimental command")),
-                    | _ => Ok(()),
-                }
+                // there are currently no experimental commands
+                Ok(())
             },
             | Privilege::Experimental => Ok(()),
         }
@@ -50,9 +48,24 @@ pub enum Command {
     Init,
     Schema,
     Render(crate::render::RenderArguments),
+    Check(crate::render::CheckArguments),
+    List(crate::describe::ListArguments),
+    Describe(crate::describe::DescribeArguments),
+    Validate(crate::lint::ValidateArguments),
+    Trust(crate::render::trust::TrustArguments),
     Direct(crate::render::DirectArguments),
 }
 
+/// The arguments shared by all commands that render a template of the
+/// configuration.
+struct TemplateArgs {
+    config: PathBuf,
+    template: Option<String>,
+    shell_trust: crate::render::ShellTrust,
+    loose: bool,
+    value_overrides: crate::render::values::Overrides,
+}
+
 pub struct ClapArgumentLoader {}
 
 impl ClapArgumentLoader {
@@ -105,27 +118,119 @@ impl ClapArgumentLoader {
             .subcommand(
                 clap::Command::new("render")
                     .about("Renders a template by replacing values as specified by the configuration.")
+                    .args(Self::template_args())
+                    .arg(
+                        clap::Arg::new("backend")
+                            .short('b')
+                            .long("backend")
+                            .help("The execution backend (cli=native-terminal, ui=ui emulator in terminal).")
+                            .value_parser(backend_values.clone())
+                            .default_value("headless"),
+                    )
+                    .arg(clap::Arg::new("out").short('o').long("out").help(
+                        "Writes the rendered content to this file instead of STDOUT. The file is only (atomically) \
+                         replaced if rendering succeeded.",
+                    ))
+                    .arg(Self::block_arg())
+                    .arg(clap::Arg::new("plan").long("plan").action(ArgAction::SetTrue).help(
+                        "Prints how every variable would be resolved headlessly without executing shell commands or \
+                         prompts. Fails if headless rendering would fail.",
+                    ))
+                    .arg(clap::Arg::new("record").long("record").help(
+                        "Records all resolved values to this answers file (JSON if the file has a .json extension, \
+                         YAML otherwise).",
+                    ))
                     .arg(
-                        clap::Arg::new("config")
-                            .short('c')
-                            .long("config")
-                            .help("The configuration file to use.")
-                            .default_value("./.complate/config.yaml"),
+                        clap::Arg::new("target-dir")
+                            .long("target-dir")
+                            .help("The directory into which the files of directory templates are written.")
+                            .default_value("."),
                     )
+                    .arg(
+                        clap::Arg::new("overwrite")
+                            .long("overwrite")
+                            .help("Defines what happens with existing files when rendering directory templates.")
+                            .value_parser(["fail", "skip", "overwrite", "prompt"])
+                            .default_value("fail"),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("check")
+                    .about(
+                        "Renders a template headlessly and compares the result with an existing file. Prints a diff \
+                         and fails if they differ.",
+                    )
+                    .args(Self::template_args())
+                    .arg(
+                        clap::Arg::new("file")
+                            .short('f')
+                            .long("file")
+                            .help("The file to compare with. Defaults to the output path of the template."),
+                    )
+                    .arg(Self::block_arg()),
+            )
+            .subcommand(
+                clap::Command::new("list")
+                    .about("Lists the templates of the configuration with their descriptions.")
+                    .arg(Self::config_arg())
+                    .arg(Self::json_arg()),
+            )
+            .subcommand(
+                clap::Command::new("describe")
+                    .about(
+                        "Describes a template with its variables, their sources and whether they can be resolved \
+                         headlessly.",
+                    )
+                    .arg(Self::config_arg())
+                    .arg(
+                        clap::Arg::new("template")
+                            .required(true)
+                            .help("The template to describe."),
+                    )
+                    .arg(Self::json_arg()),
+            )
+            .subcommand(
+                clap::Command::new("validate")
+                    .about(
+                        "Validates the configuration and all of its templates (syntax, variables, helpers, partials, \
+                         files and shell usage).",
+                    )
+                    .arg(Self::config_arg()),
+            )
+            .subcommand(
+                clap::Command::new("trust")
+                    .about(
+                        "Approves the configuration (and all included files) in their current state by recording \
+                         their hashes in a trust policy file.",
+                    )
+                    .arg(Self::config_arg())
+                    .arg(
+                        clap::Arg::new("policy")
+                            .short('p')
+                            .long("policy")
+                            .required(true)
+                            .help("The trust policy file (created if it does not exist)."),
+                    ),
+            )
+            .subcommand(
+                clap::Command::new("direct")
+                    .about("Simply renders a template with values files. No fancy business here.")
                     .arg(
                         clap::Arg::new("template")
                             .short('t')
                             .long("template")
-                            .help("Specify the template to use from the config and skip it's selection."),
+                            .required(true)
+                            .help("Template file (\"-\" for STDIN)."),
                     )
                     .arg(
-                        clap::Arg::new("trust")
-                            .long("trust")
+                        clap::Arg::new("values")
+                            .short('v')
+                            .long("values")
+                            .action(ArgAction::Append)
                             .help(
-                                "Enables the shell command execution. This is potentially insecure and should only be \
-                                 done for trustworthy sources.",
-                            )
-                            .action(ArgAction::SetTrue),
+                                "Values file (\"-\" for STDIN). YAML, JSON and TOML files are detected by the \
+                                 extension. Multiple files are deep merged in order.",
+                            ),
                     )
                     .arg(
                         clap::Arg::new("loose")
@@ -137,33 +242,91 @@ impl ClapArgumentLoader {
                                  variable).",
                             ),
                     )
+                    .arg(clap::Arg::new("out").short('o').long("out").help(
+                        "Writes the rendered content to this file instead of STDOUT. The file is only (atomically) \
+                         replaced if rendering succeeded.",
+                    ))
+                    .arg(Self::block_arg())
                     .arg(
-                        clap::Arg::new("backend")
-                            .short('b')
-                            .long("backend")
-                            .help("The execution backend (cli=native-terminal, ui=ui emulator in terminal).")
-                            .value_parser(backend_values.clone())
-                            .default_value("headless"),
-                    )
-                    .arg(
-                        clap::Arg::new("value")
-                            .short('v')
-                            .long("value")
-                            .action(ArgAction::Append)
-                            .help("Overrides a certain value definition with a string."),
+                        clap::Arg::new("escape")
+                            .long("escape")
+                            .value_parser(["none", "html", "xml", "json", "yaml", "shell"])
+                            .default_value("none")
+                            .help("How values are escaped when they are rendered with {{ }}."),
                     ),
             )
-            .subcommand(
-                clap::Command::new("direct")
-                    .about("Simply renders a template with a values file. No fancy business here.")
-                    .arg(
-                        clap::Arg::new("template")
-                            .short('t')
-                            .long("template")
-                            .help("Template file."),
-                    )
-                    .arg(clap::Arg::new("values").short('v').long("values").help("Values file.")),
-            )
+    }
+
+    /// Arguments that select a template and provide the values to render it
+    /// with.
+    fn template_args() -> Vec<Arg> {
+        vec![
+            Self::config_arg(),
+            clap::Arg::new("template")
+                .short('t')
+                .long("template")
+                .help("Specify the template to use from the config and skip it's selection."),
+            clap::Arg::new("trust")
+                .long("trust")
+                .help(
+                    "Enables the shell command execution. This is potentially insecure and should only be done for \
+                     trustworthy sources.",
+                )
+                .action(ArgAction::SetTrue),
+            clap::Arg::new("trust-policy")
+                .long("trust-policy")
+                .conflicts_with("trust")
+                .help(
+                    "Only executes the shell commands that are allowed by this trust policy file. The configuration \
+                     files must have been approved with the trust command.",
+                ),
+            clap::Arg::new("loose")
+                .short('l')
+                .long("loose")
+                .action(ArgAction::SetTrue)
+                .help("Defines that the templating is done in non-strict mode (allow missing value for variable)."),
+            clap::Arg::new("value")
+                .short('v')
+                .long("value")
+                .action(ArgAction::Append)
+                .help(
+                    "Overrides a certain value definition with a string (key=value). Takes precedence over \
+                     --values-file and --replay.",
+                ),
+            clap::Arg::new("values-file")
+                .long("values-file")
+                .action(ArgAction::Append)
+                .help(
+                    "Overrides the value definitions with the values of a YAML, JSON or TOML file (detected by the \
+                     extension). Nested mappings are flattened to dotted variable names.",
+                ),
+            clap::Arg::new("replay").long("replay").help(
+                "Uses the values of an answers file (see --record) as overrides. Values given with --value take \
+                 precedence.",
+            ),
+        ]
+    }
+
+    fn config_arg() -> Arg {
+        clap::Arg::new("config")
+            .short('c')
+            .long("config")
+            .help("The configuration file to use.")
+            .default_value("./.complate/config.yaml")
+    }
+
+    fn json_arg() -> Arg {
+        clap::Arg::new("json")
+            .long("json")
+            .action(ArgAction::SetTrue)
+            .help("Prints the output as JSON.")
+    }
+
+    fn block_arg() -> Arg {
+        clap::Arg::new("block").long("block").help(
+            "Only replaces the content between the \"complate:begin <block>\" and \"complate:end <block>\" markers of \
+             the existing output file.",
+        )
     }
 
     pub async fn load() -> Result<CallArgs> {
@@ -209,28 +372,85 @@ impl ClapArgumentLoader {
         } else if let Some(subc) = command_matches.subcommand_matches("direct") {
             Ok(CallArgs {
                 command: Command::Direct(DirectArguments {
+                    template: subc.get_one::<String>("template").unwrap().into(),
+                    values: subc
+                        .get_many::<String>("values")
+                        .map(|v| v.map(|x| x.into()).collect())
+                        .unwrap_or_default(),
+                    loose: subc.get_flag("loose"),
+                    out: subc.get_one::<String>("out").map(|v| v.into()),
+                    block: subc.get_one::<String>("block").cloned(),
+                    escape: match subc.get_one::<String>("escape").unwrap().as_str() {
+                        | "html" => crate::config::EscapeMode::Html,
+                        | "xml" => crate::config::EscapeMode::Xml,
+                        | "json" => crate::config::EscapeMode::Json,
+                        | "yaml" => crate::config::EscapeMode::Yaml,
+                        | "shell" => crate::config::EscapeMode::Shell,
+                        | _ => crate::config::EscapeMode::None,
+                    },
+                }),
+                privileges,
+            })
+        } else if let Some(subc) = command_matches.subcommand_matches("list") {
+            Ok(CallArgs {
+                command: Command::List(crate::describe::ListArguments {
+                    configuration: subc.get_one::<String>("config").unwrap().into(),
+                    json: subc.get_flag("json"),
+                }),
+                privileges,
+            })
+        } else if let Some(subc) = command_matches.subcommand_matches("describe") {
+            Ok(CallArgs {
+                command: Command::Describe(crate::describe::DescribeArguments {
+                    configuration: subc.get_one::<String>("config").unwrap().into(),
                     template: subc.get_one::<String>("template").unwrap().to_owned(),
-                    values: subc.get_one::<String>("values").unwrap().to_owned(),
+                    json: subc.get_flag("json"),
+                }),
+                privileges,
+            })
+        } else if let Some(subc) = command_matches.subcommand_matches("validate") {
+            Ok(CallArgs {
+                command: Command::Validate(crate::lint::ValidateArguments {
+                    configuration: subc.get_one::<String>("config").unwrap().into(),
                 }),
                 privileges,
             })
+        } else if let Some(subc) = command_matches.subcommand_matches("trust") {
+            Ok(CallArgs {
+                command: Command::Trust(crate::render::trust::TrustArguments {
+                    configuration: subc.get_one::<String>("config").unwrap().into(),
+                    policy: subc.get_one::<String>("policy").unwrap().into(),
+                }),
+                privileges,
+            })
+        } else if let Some(subc) = command_matches.subcommand_matches("check") {
+            let TemplateArgs {
+                config,
+                template,
+                shell_trust,
+                loose,
+                value_overrides,
+            } = Self::parse_template_args(subc)?;
+            Ok(CallArgs {
+                privileges,
+                command: Command::Check(crate::render::CheckArguments {
+                    configuration: config,
+                    template: template.ok_or_else(|| anyhow::anyhow!("template must be specified"))?,
+                    value_overrides,
+                    shell_trust,
+                    loose,
+                    file: subc.get_one::<String>("file").map(|v| v.into()),
+                    block: subc.get_one::<String>("block").cloned(),
+                }),
+            })
         } else if let Some(subc) = command_matches.subcommand_matches("render") {
-            let config = std::fs::read_to_string(subc.get_one::<String>("config").unwrap())?;
-            let template = subc.get_one::<String>("template").map(|v| v.into());
-            let shell_trust = if subc.get_flag("trust") {
-                crate::render::ShellTrust::Ultimate
-            } else {
-                crate::render::ShellTrust::None
-            };
-            let loose = subc.get_flag("loose");
-
-            let mut value_overrides = HashMap::<String, String>::new();
-            if let Some(vo_arg) = subc.get_many::<String>("value") {
-                for vo in vo_arg {
-                    let spl = vo.splitn(2, "=").collect::<Vec<_>>();
-                    value_overrides.insert(spl[0].into(), spl[1].into());
-                }
-            }
+            let TemplateArgs {
+                config,
+                template,
+                shell_trust,
+                loose,
+                value_overrides,
+            } = Self::parse_template_args(subc)?;
             let backend = match subc.get_one::<String>("backend").unwrap().as_str() {
                 | "headless" => crate::render::Backend::Headless,
                 #[cfg(feature = "backend+cli")]
@@ -238,6 +458,16 @@ impl ClapArgumentLoader {
                 | _ => return Err(anyhow::anyhow!("no backend specified")),
             };
 
+            let target_dir = subc.get_one::<String>("target-dir").unwrap().into();
+            let out = subc.get_one::<String>("out").map(|v| v.into());
+            let overwrite = match subc.get_one::<String>("overwrite").unwrap().as_str() {
+                | "fail" => crate::render::OverwritePolicy::Fail,
+                | "skip" => crate::render::OverwritePolicy::Skip,
+                | "overwrite" => crate::render::OverwritePolicy::Overwrite,
+                | "prompt" => crate::render::OverwritePolicy::Prompt,
+                | _ => return Err(anyhow::anyhow!("unknown overwrite policy")),
+            };
+
             Ok(CallArgs {
                 privileges,
                 command: Command::Render(crate::render::RenderArguments {
@@ -247,10 +477,56 @@ impl ClapArgumentLoader {
                     shell_trust,
                     loose,
                     backend,
+                    target_dir,
+                    overwrite,
+                    out,
+                    block: subc.get_one::<String>("block").cloned(),
+                    record: subc.get_one::<String>("record").map(|v| v.into()),
+                    plan: subc.get_flag("plan"),
                 }),
             })
         } else {
             return Err(anyhow::anyhow!("unknown command"));
         }
     }
+
+    fn parse_template_args(subc: &clap::ArgMatches) -> Result<TemplateArgs> {
+        let config = subc.get_one::<String>("config").unwrap().into();
+        let template = subc.get_one::<String>("template").map(|v| v.into());
+        let shell_trust = if subc.get_flag("trust") {
+            crate::render::ShellTrust::Ultimate
+        } else if let Some(policy) = subc.get_one::<String>("trust-policy") {
+            crate::render::ShellTrust::Policy(crate::render::trust::TrustPolicy::load(std::path::Path::new(policy))?)
+        } else {
+            crate::render::ShellTrust::None
+        };
+        let loose = subc.get_flag("loose");
+
+        let mut value_overrides = crate::render::values::Overrides::default();
+        if let Some(x) = subc.get_one::<String>("replay") {
+            value_overrides.answers = crate::render::answers::load(std::path::Path::new(x))?;
+        }
+        if let Some(files) = subc.get_many::<String>("values-file") {
+            for file in files {
+                value_overrides
+                    .files
+                    .push(crate::render::values::load(std::path::Path::new(file))?);
+            }
+        }
+        if let Some(vo_arg) = subc.get_many::<String>("value") {
+            for vo in vo_arg {
+                match vo.split_once('=') {
+                    | Some((k, v)) if !k.is_empty() => value_overrides.values.insert(k.into(), v.into()),
+                    | _ => return Err(anyhow::anyhow!("invalid value \"{}\" (expected key=value)", vo)),
+                };
+            }
+        }
+        Ok(TemplateArgs {
+            config,
+            template,
+            shell_trust,
+            loose,
+            value_overrides,
+        })
+    }
 }
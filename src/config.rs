@@ -1,56 +1,103 @@
-use std::collections::{
-    BTreeMap,
-    HashMap,
+use {
+    anyhow::Result,
+    fancy_regex::Regex,
+    indexmap::IndexMap,
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        convert::TryFrom,
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
 };
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Config {
     pub version: String,
-    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    /// Paths or glob patterns (relative to the including file) of further
+    /// configuration files whose templates are merged into this
+    /// configuration.
+    pub includes: std::option::Option<Vec<String>>,
+    /// Named template fragments that are available in every template via `{{>
+    /// name}}`.
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
+    #[schemars(with = "std::option::Option<BTreeMap<String, Content>>")]
+    pub partials: std::option::Option<BTreeMap<String, Content>>,
+    /// Helpers that are inherited by every template. Helpers of the templates
+    /// override them.
+    #[schemars(with = "std::option::Option<BTreeMap<String, HelperDefinition>>")]
+    pub helpers: std::option::Option<BTreeMap<String, HelperDefinition>>,
+    /// Variables that are inherited by every template. They are resolved for
+    /// every rendered template, variables of the templates override them.
+    #[schemars(with = "std::option::Option<HashMap<String, VariableSchema>>")]
+    pub variables: std::option::Option<IndexMap<String, Variable>>,
+    #[serde(default, with = "serde_yaml::with::singleton_map_recursive")]
     #[schemars(with = "BTreeMap<String, Template>")]
     pub templates: BTreeMap<String, Template>,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub struct Template {
-    pub content: Content,
-    #[schemars(with = "std::option::Option<HashMap<String, VariableDefinition>>")]
-    pub variables: std::option::Option<HashMap<String, VariableDefinition>>,
-    #[schemars(with = "std::option::Option<HashMap<String, String>>")]
-    pub helpers: std::option::Option<HashMap<String, String>>,
+    /// Short human readable description that is shown by `list` and
+    /// `describe`.
+    pub description: std::option::Option<String>,
+    /// Name of a parent template whose content, variables and helpers are
+    /// inherited. Entries defined on this template override the inherited
+    /// ones.
+    pub extends: std::option::Option<String>,
+    pub content: std::option::Option<Content>,
+    /// Path of the file that the rendered content is written to instead of
+    /// STDOUT. The path is a template itself.
+    pub output: std::option::Option<String>,
+    #[schemars(with = "std::option::Option<HashMap<String, VariableSchema>>")]
+    /// Variables are resolved (and prompted for) in the order of their
+    /// declaration unless `depends_on` requires otherwise.
+    pub variables: std::option::Option<IndexMap<String, Variable>>,
+    #[schemars(with = "std::option::Option<HashMap<String, HelperDefinition>>")]
+    pub helpers: std::option::Option<HashMap<String, HelperDefinition>>,
+    /// How values are escaped when they are rendered with `{{ }}` (default:
+    /// `none`).
+    pub escape: std::option::Option<EscapeMode>,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum Content {
     File(String),
     Inline(String),
+    /// A directory tree that is rendered into a target directory. Both the
+    /// paths and the contents of the files are templates.
+    Directory(String),
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum OptionValue {
     Static(String),
-    Shell(String),
+    Shell(ShellCommand),
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub struct Option {
     pub display: String,
     pub value: OptionValue,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum VariableDefinition {
     Arg,
     Env(String),
     Static(String),
     Prompt(String),
-    Shell(String),
+    Shell(ShellCommand),
     Select {
         text: String,
         #[schemars(with = "BTreeMap<String, Option>")]
@@ -62,8 +109,775 @@ pub enum VariableDefinition {
         #[schemars(with = "BTreeMap<String, Option>")]
         options: BTreeMap<String, Option>,
     },
+    /// Resolves to the value of the first definition in the list that can be
+    /// resolved, e.g. an env var with a prompt and a static default as
+    /// fallbacks.
+    FirstOf(Vec<VariableDefinition>),
+}
+
+impl VariableDefinition {
+    /// The name of the source as it is written in the configuration.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            | VariableDefinition::Arg => "arg",
+            | VariableDefinition::Env(..) => "env",
+            | VariableDefinition::Static(..) => "static",
+            | VariableDefinition::Prompt(..) => "prompt",
+            | VariableDefinition::Shell(..) => "shell",
+            | VariableDefinition::Select { .. } => "select",
+            | VariableDefinition::Check { .. } => "check",
+            | VariableDefinition::FirstOf(..) => "first_of",
+        }
+    }
+
+    /// Whether resolving the value executes shell commands (and therefore
+    /// needs `--trust`).
+    pub fn requires_trust(&self) -> bool {
+        match self {
+            | VariableDefinition::Shell(..) => true,
+            | VariableDefinition::Select { options, .. } | VariableDefinition::Check { options, .. } => {
+                options.values().any(|o| matches!(o.value, OptionValue::Shell(..)))
+            },
+            | VariableDefinition::FirstOf(definitions) => {
+                !definitions.is_empty() && definitions.iter().all(|d| d.requires_trust())
+            },
+            | _ => false,
+        }
+    }
+
+    /// Whether the headless backend can resolve the value without an override.
+    /// Arguments are only given as overrides and prompts require user
+    /// interaction.
+    pub fn resolvable_headless(&self) -> bool {
+        match self {
+            | VariableDefinition::Arg
+            | VariableDefinition::Prompt(..)
+            | VariableDefinition::Select { .. }
+            | VariableDefinition::Check { .. } => false,
+            | VariableDefinition::FirstOf(definitions) => definitions.iter().any(|d| d.resolvable_headless()),
+            | _ => true,
+        }
+    }
+}
+
+/// A variable is defined by its source (see `VariableDefinition`) and optional
+/// attributes that are declared next to the source key, e.g. `{ env: "ENABLED",
+/// type: "bool" }`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "serde_yaml::Value", into = "serde_yaml::Value")]
+pub struct Variable {
+    pub source: VariableDefinition,
+    pub attributes: VariableAttributes,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct VariableAttributes {
+    /// The type that the resolved value is parsed into before it is handed to
+    /// the template. Defaults to `string`.
+    #[serde(rename = "type", skip_serializing_if = "std::option::Option::is_none")]
+    pub value_type: std::option::Option<ValueType>,
+    /// Variables that are resolved before this one. Their values are passed to
+    /// shell commands as env vars (with every character that is not
+    /// alphanumeric replaced by `_`) and prompt texts can use them as template
+    /// values.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub depends_on: std::option::Option<Vec<String>>,
+    /// Rules that the resolved value has to satisfy, regardless of its source.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub validation: std::option::Option<Validation>,
+}
+
+impl VariableAttributes {
+    const KEYS: &'static [&'static str] = &["type", "depends_on", "validation"];
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Validation {
+    /// The value must not be empty or consist of whitespace only.
+    pub non_empty: std::option::Option<bool>,
+    /// The value must match the regular expression.
+    pub regex: std::option::Option<String>,
+    pub min_length: std::option::Option<usize>,
+    pub max_length: std::option::Option<usize>,
+    /// The value must be a number that is greater than or equal to `min`.
+    pub min: std::option::Option<f64>,
+    /// The value must be a number that is less than or equal to `max`.
+    pub max: std::option::Option<f64>,
+}
+
+impl Validation {
+    /// Checks the value against all rules and returns a description of the
+    /// first violated rule.
+    pub fn check(&self, value: &str) -> std::result::Result<(), String> {
+        if self.non_empty == Some(true) && value.trim().is_empty() {
+            return Err("rule \"non_empty\" violated: value is empty".into());
+        }
+        if let Some(regex) = &self.regex {
+            let re = Regex::new(regex).map_err(|e| format!("rule \"regex\" is invalid: {}", e))?;
+            if !re.is_match(value).map_err(|e| e.to_string())? {
+                return Err(format!("rule \"regex\" violated: value does not match {}", regex));
+            }
+        }
+        let length = value.chars().count();
+        if let Some(min_length) = self.min_length {
+            if length < min_length {
+                return Err(format!(
+                    "rule \"min_length\" violated: value has {} characters, expected at least {}",
+                    length, min_length
+                ));
+            }
+        }
+        if let Some(max_length) = self.max_length {
+            if length > max_length {
+                return Err(format!(
+                    "rule \"max_length\" violated: value has {} characters, expected at most {}",
+                    length, max_length
+                ));
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            let number = value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("rule \"min\"/\"max\" violated: value {} is not a number", value))?;
+            if let Some(min) = self.min {
+                if number < min {
+                    return Err(format!("rule \"min\" violated: {} is less than {}", number, min));
+                }
+            }
+            if let Some(max) = self.max {
+                if number > max {
+                    return Err(format!("rule \"max\" violated: {} is greater than {}", number, max));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum ValueType {
+    String,
+    Bool,
+    Integer,
+    Float,
+    /// A YAML (or JSON) sequence.
+    List,
+    /// A YAML (or JSON) mapping.
+    Object,
+}
+
+/// Escaping of rendered values so that the generated content stays
+/// syntactically valid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum EscapeMode {
+    #[default]
+    None,
+    Html,
+    Xml,
+    /// Escapes the value for a double quoted JSON string.
+    Json,
+    /// Escapes the value for a double quoted YAML string.
+    Yaml,
+    /// Quotes the value as a single shell word.
+    Shell,
+}
+
+/// Schema representation of `Variable` as it is written in the configuration
+/// file.
+#[derive(schemars::JsonSchema)]
+#[schemars(rename = "Variable")]
+#[allow(dead_code)]
+pub struct VariableSchema {
+    #[serde(flatten)]
+    source: VariableDefinition,
+    #[serde(flatten)]
+    attributes: VariableAttributes,
+}
+
+impl TryFrom<serde_yaml::Value> for Variable {
+    type Error = serde_yaml::Error;
+
+    fn try_from(value: serde_yaml::Value) -> std::result::Result<Self, Self::Error> {
+        let mut attributes = serde_yaml::Mapping::new();
+        let source = match value {
+            | serde_yaml::Value::Mapping(mut mapping) => {
+                for key in VariableAttributes::KEYS {
+                    if let Some(v) = mapping.remove(*key) {
+                        attributes.insert((*key).into(), v);
+                    }
+                }
+                if mapping.len() != 1 {
+                    let keys = mapping
+                        .keys()
+                        .map(|k| serde_yaml::to_string(k).unwrap_or_default().trim().to_owned())
+                        .collect::<Vec<_>>();
+                    return Err(serde::de::Error::custom(format!(
+                        "expected exactly one variable source, found [{}]",
+                        keys.join(", ")
+                    )));
+                }
+                serde_yaml::Value::Mapping(mapping)
+            },
+            | v => v,
+        };
+
+        Ok(Self {
+            source: serde_yaml::with::singleton_map_recursive::deserialize(source)?,
+            attributes: serde_yaml::from_value(serde_yaml::Value::Mapping(attributes))?,
+        })
+    }
+}
+
+impl From<Variable> for serde_yaml::Value {
+    fn from(variable: Variable) -> Self {
+        let source =
+            serde_yaml::with::singleton_map_recursive::serialize(&variable.source, serde_yaml::value::Serializer)
+                .unwrap();
+        let attributes = match serde_yaml::to_value(&variable.attributes).unwrap() {
+            | serde_yaml::Value::Mapping(x) if !x.is_empty() => x,
+            | _ => return source,
+        };
+
+        let mut mapping = match source {
+            | serde_yaml::Value::Mapping(x) => x,
+            | x => {
+                let mut m = serde_yaml::Mapping::new();
+                m.insert(x, serde_yaml::Value::Null);
+                m
+            },
+        };
+        mapping.extend(attributes);
+        serde_yaml::Value::Mapping(mapping)
+    }
+}
+
+/// A shell command. It is either written as a plain string or as a mapping with
+/// the `command` and its execution settings, e.g. `{ command: "git describe",
+/// timeout: 5 }`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "serde_yaml::Value", into = "serde_yaml::Value")]
+pub struct ShellCommand {
+    pub command: String,
+    pub settings: ShellSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct ShellSettings {
+    /// The number of seconds after which the command is killed.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub timeout: std::option::Option<u64>,
+    /// The working directory of the command. Defaults to the current
+    /// directory.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub cwd: std::option::Option<String>,
+    /// Additional env vars of the command.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub env: std::option::Option<BTreeMap<String, String>>,
+    /// Whether the command inherits the env vars of complate. Defaults to
+    /// `true`.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub inherit_env: std::option::Option<bool>,
+    /// The shell that executes the command with `-c`. Defaults to `sh`.
+    #[serde(skip_serializing_if = "std::option::Option::is_none")]
+    pub shell: std::option::Option<String>,
+}
+
+impl ShellCommand {
+    const COMMAND_KEY: &'static str = "command";
+}
+
+/// Schema representation of `ShellCommand` as it is written in the
+/// configuration file.
+#[derive(schemars::JsonSchema)]
+#[schemars(rename = "ShellCommand")]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum ShellCommandSchema {
+    Command(String),
+    Settings {
+        command: String,
+        #[serde(flatten)]
+        settings: ShellSettings,
+    },
+}
+
+impl schemars::JsonSchema for ShellCommand {
+    fn schema_name() -> String {
+        ShellCommandSchema::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        ShellCommandSchema::json_schema(gen)
+    }
+}
+
+impl TryFrom<serde_yaml::Value> for ShellCommand {
+    type Error = serde_yaml::Error;
+
+    fn try_from(value: serde_yaml::Value) -> std::result::Result<Self, Self::Error> {
+        match value {
+            | serde_yaml::Value::Mapping(mut mapping) => {
+                let command = match mapping.remove(Self::COMMAND_KEY) {
+                    | Some(serde_yaml::Value::String(x)) => x,
+                    | _ => return Err(serde::de::Error::custom("expected a \"command\" string")),
+                };
+                Ok(Self {
+                    command,
+                    settings: serde_yaml::from_value(serde_yaml::Value::Mapping(mapping))?,
+                })
+            },
+            | v => {
+                Ok(Self {
+                    command: serde_yaml::from_value(v)?,
+                    settings: ShellSettings::default(),
+                })
+            },
+        }
+    }
+}
+
+impl From<ShellCommand> for serde_yaml::Value {
+    fn from(command: ShellCommand) -> Self {
+        let mut mapping = match serde_yaml::to_value(&command.settings).unwrap() {
+            | serde_yaml::Value::Mapping(x) if !x.is_empty() => x,
+            | _ => return serde_yaml::Value::String(command.command),
+        };
+        mapping.insert(ShellCommand::COMMAND_KEY.into(), command.command.into());
+        serde_yaml::Value::Mapping(mapping)
+    }
+}
+
+/// A template helper. Strings and mappings with a `command` are shell commands,
+/// mappings with a `script` are Rhai scripts.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "serde_yaml::Value", into = "serde_yaml::Value")]
+pub enum HelperDefinition {
+    Shell(ShellCommand),
+    /// A Rhai script that is executed in-process. Scripts have no access to the
+    /// filesystem, env vars or processes and therefore do not require trust.
+    Script(String),
+}
+
+impl HelperDefinition {
+    const SCRIPT_KEY: &'static str = "script";
+
+    pub fn requires_trust(&self) -> bool {
+        matches!(self, HelperDefinition::Shell(..))
+    }
+}
+
+/// Schema representation of `HelperDefinition` as it is written in the
+/// configuration file.
+#[derive(schemars::JsonSchema)]
+#[schemars(rename = "HelperDefinition")]
+#[serde(untagged)]
+#[allow(dead_code)]
+enum HelperDefinitionSchema {
+    Shell(ShellCommand),
+    Script {
+        /// The Rhai script. Its result is the output of the helper.
+        script: String,
+    },
+}
+
+impl schemars::JsonSchema for HelperDefinition {
+    fn schema_name() -> String {
+        HelperDefinitionSchema::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        HelperDefinitionSchema::json_schema(gen)
+    }
+}
+
+impl TryFrom<serde_yaml::Value> for HelperDefinition {
+    type Error = serde_yaml::Error;
+
+    fn try_from(value: serde_yaml::Value) -> std::result::Result<Self, Self::Error> {
+        match value {
+            | serde_yaml::Value::Mapping(mut mapping) if mapping.contains_key(Self::SCRIPT_KEY) => {
+                let script = match mapping.remove(Self::SCRIPT_KEY) {
+                    | Some(serde_yaml::Value::String(x)) => x,
+                    | _ => return Err(serde::de::Error::custom("expected a \"script\" string")),
+                };
+                if let Some((key, _)) = mapping.iter().next() {
+                    return Err(serde::de::Error::custom(format!(
+                        "unknown field {:?} of script helper",
+                        key.as_str().unwrap_or_default()
+                    )));
+                }
+                Ok(Self::Script(script))
+            },
+            | v => Ok(Self::Shell(ShellCommand::try_from(v)?)),
+        }
+    }
+}
+
+impl From<HelperDefinition> for serde_yaml::Value {
+    fn from(helper: HelperDefinition) -> Self {
+        match helper {
+            | HelperDefinition::Shell(command) => command.into(),
+            | HelperDefinition::Script(script) => {
+                let mut mapping = serde_yaml::Mapping::new();
+                mapping.insert(HelperDefinition::SCRIPT_KEY.into(), script.into());
+                serde_yaml::Value::Mapping(mapping)
+            },
+        }
+    }
+}
+
+/// Where an inherited entry (variable or helper) of a template is defined.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    /// The top level of the configuration.
+    Global,
+    /// The template itself or one of the templates that it extends.
+    Template(String),
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            | Origin::Global => write!(f, "global"),
+            | Origin::Template(t) => write!(f, "template {}", t),
+        }
+    }
+}
+
+impl Config {
+    /// Returns the template with the given name with its `extends` chain and
+    /// the global helpers and variables applied. Only the global variables that
+    /// the template refers to (directly or with `depends_on`) are inherited.
+    pub fn resolve_template(&self, name: &str) -> Result<Template> {
+        let chain = self.template_chain(name)?;
+        let mut resolved = Template {
+            description: None,
+            extends: None,
+            content: None,
+            output: None,
+            variables: self.variables.clone(),
+            helpers: self.helpers.as_ref().map(|h| h.clone().into_iter().collect()),
+            escape: None,
+        };
+        for t in chain.iter().rev().map(|t| &self.templates[*t]) {
+            if let Some(description) = &t.description {
+                resolved.description = Some(description.clone());
+            }
+            if let Some(content) = &t.content {
+                resolved.content = Some(content.clone());
+            }
+            if let Some(output) = &t.output {
+                resolved.output = Some(output.clone());
+            }
+            if let Some(variables) = &t.variables {
+                resolved
+                    .variables
+                    .get_or_insert_with(IndexMap::new)
+                    .extend(variables.clone());
+            }
+            if let Some(helpers) = &t.helpers {
+                resolved
+                    .helpers
+                    .get_or_insert_with(HashMap::new)
+                    .extend(helpers.clone());
+            }
+            if let Some(escape) = t.escape {
+                resolved.escape = Some(escape);
+            }
+        }
+
+        if resolved.content.is_none() {
+            return Err(anyhow::anyhow!(
+                "template \"{}\" has no content (resolution chain: {})",
+                name,
+                chain.join(" -> ")
+            ));
+        }
+
+        let locals = chain
+            .iter()
+            .flat_map(|t| self.templates[*t].variables.iter().flatten().map(|v| v.0))
+            .collect::<Vec<_>>();
+        if let (Some(variables), Some(references)) =
+            (&resolved.variables, crate::lint::referenced_variables(self, &resolved))
+        {
+            let mut used = variables
+                .keys()
+                .filter(|v| locals.contains(v) || references.iter().any(|r| crate::lint::refers_to(r, v)))
+                .collect::<Vec<_>>();
+            let mut i = 0;
+            while i < used.len() {
+                for dependency in variables
+                    .get(used[i])
+                    .and_then(|v| v.attributes.depends_on.as_ref())
+                    .into_iter()
+                    .flatten()
+                {
+                    if !used.contains(&dependency) {
+                        used.push(dependency);
+                    }
+                }
+                i += 1;
+            }
+            resolved.variables = Some(
+                variables
+                    .iter()
+                    .filter(|(v, _)| used.contains(v))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            );
+        }
+        Ok(resolved)
+    }
+
+    /// Returns the names of the template and all templates that it
+    /// (transitively) extends, starting with the template itself.
+    pub fn template_chain<'a>(&'a self, name: &'a str) -> Result<Vec<&'a str>> {
+        let mut chain = Vec::<&str>::new();
+        let mut current = name;
+        loop {
+            if chain.contains(&current) {
+                chain.push(current);
+                return Err(anyhow::anyhow!("cyclic template inheritance: {}", chain.join(" -> ")));
+            }
+            chain.push(current);
+
+            let template = match self.templates.get(current) {
+                | Some(x) => x,
+                | None if chain.len() == 1 => return Err(anyhow::anyhow!("template not found")),
+                | None => {
+                    return Err(anyhow::anyhow!(
+                        "template \"{}\" extends unknown template \"{}\" (resolution chain: {})",
+                        chain[chain.len() - 2],
+                        current,
+                        chain.join(" -> ")
+                    ))
+                },
+            };
+            match &template.extends {
+                | Some(parent) => current = parent,
+                | None => break,
+            }
+        }
+
+        Ok(chain)
+    }
+
+    /// Returns where the variable or helper (`kind`) of the resolved template
+    /// is defined.
+    pub fn origin(&self, template: &str, kind: &str, name: &str) -> Result<Origin> {
+        let defines = |t: &Template| {
+            match kind {
+                | "variable" => t.variables.as_ref().map(|v| v.contains_key(name)).unwrap_or(false),
+                | _ => t.helpers.as_ref().map(|h| h.contains_key(name)).unwrap_or(false),
+            }
+        };
+        Ok(self
+            .template_chain(template)?
+            .into_iter()
+            .find(|t| defines(&self.templates[*t]))
+            .map(|t| Origin::Template(t.to_owned()))
+            .unwrap_or(Origin::Global))
+    }
 }
 
 pub async fn default_config() -> &'static str {
     include_str!("../.complate/config.yaml")
 }
+
+/// Loads the configuration file at the given path and merges the templates,
+/// partials and global helpers and variables of all (transitively) included
+/// files into it. Each name may only be defined once across all files.
+pub async fn load(path: &Path) -> Result<Config> {
+    Ok(load_with_sources(path).await?.config)
+}
+
+/// A configuration together with the files it was loaded from.
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// The file that each template, partial and global helper and variable
+    /// (keyed by `("template" | "partial" | "helper" | "variable", name)`) is
+    /// defined in.
+    pub origins: BTreeMap<(&'static str, String), PathBuf>,
+    /// The canonical paths of the configuration file and all (transitively)
+    /// included files.
+    pub files: Vec<PathBuf>,
+}
+
+/// Like `load` but additionally returns where the templates and partials are
+/// defined.
+pub async fn load_with_sources(path: &Path) -> Result<LoadedConfig> {
+    let mut config = load_file(path)?;
+    let mut origins = BTreeMap::<(&'static str, String), PathBuf>::new();
+    for name in config.templates.keys() {
+        origins.insert(("template", name.to_owned()), path.to_owned());
+    }
+    for name in config.partials.iter().flat_map(|p| p.keys()) {
+        origins.insert(("partial", name.to_owned()), path.to_owned());
+    }
+    for name in config.helpers.iter().flat_map(|h| h.keys()) {
+        origins.insert(("helper", name.to_owned()), path.to_owned());
+    }
+    for name in config.variables.iter().flat_map(|v| v.keys()) {
+        origins.insert(("variable", name.to_owned()), path.to_owned());
+    }
+    let mut visited = vec![path.canonicalize()?];
+
+    if let Some(includes) = config.includes.take() {
+        include(path, &includes, &mut config, &mut origins, &mut visited)?;
+    }
+    Ok(LoadedConfig {
+        config,
+        origins,
+        files: visited,
+    })
+}
+
+fn check_version(configuration: &str) -> Result<()> {
+    #[derive(serde::Deserialize)]
+    struct WithVersion {
+        version: String,
+    }
+    let version_check: WithVersion = serde_yaml::from_str(configuration)
+        .or::<anyhow::Error>(Err(anyhow::anyhow!("config missing version field")))?;
+
+    let version_regex = Regex::new("^([0-9]+)\\.([0-9]+)$")?;
+    if !version_regex.is_match(&version_check.version)? {
+        return Err(anyhow::anyhow!("invalid version: {}", version_check.version));
+    }
+    let expected_version = env!("CARGO_PKG_VERSION").split(".").collect::<Vec<_>>()[..2].join(".");
+    if env!("CARGO_PKG_VERSION") != "0.0.0" && version_check.version != expected_version {
+        return Err(anyhow::anyhow!("config file version mismatch to binary"));
+    }
+    Ok(())
+}
+
+fn load_file(path: &Path) -> Result<Config> {
+    let configuration = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("failed to read config file {}: {}", path.display(), e))?;
+    check_version(&configuration)?;
+    serde_yaml::from_str(&configuration).map_err(|e| anyhow::anyhow!("invalid config file {}: {}", path.display(), e))
+}
+
+fn include(
+    parent: &Path,
+    patterns: &[String],
+    config: &mut Config,
+    origins: &mut BTreeMap<(&'static str, String), PathBuf>,
+    visited: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let base = parent.parent().unwrap_or(Path::new("."));
+    for pattern in patterns {
+        let full_pattern = base.join(pattern);
+        let full_pattern = full_pattern
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("invalid include pattern: {}", pattern))?;
+        let paths = glob::glob(full_pattern)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        if paths.is_empty() && !pattern.contains(&['*', '?', '['][..]) {
+            return Err(anyhow::anyhow!(
+                "included file {} (from {}) does not exist",
+                full_pattern,
+                parent.display()
+            ));
+        }
+
+        for path in paths {
+            // Files that are included more than once (e.g. by two sibling includes) are
+            // only merged once.
+            let canonical = path.canonicalize()?;
+            if visited.contains(&canonical) {
+                continue;
+            }
+            visited.push(canonical);
+
+            let mut included = load_file(&path)?;
+            rebase(&mut included, path.parent().unwrap_or(Path::new(".")));
+            merge(
+                "template",
+                &mut config.templates,
+                std::mem::take(&mut included.templates),
+                origins,
+                &path,
+            )?;
+            if let Some(partials) = included.partials.take() {
+                merge(
+                    "partial",
+                    config.partials.get_or_insert_with(BTreeMap::new),
+                    partials,
+                    origins,
+                    &path,
+                )?;
+            }
+            if let Some(helpers) = included.helpers.take() {
+                merge(
+                    "helper",
+                    config.helpers.get_or_insert_with(BTreeMap::new),
+                    helpers,
+                    origins,
+                    &path,
+                )?;
+            }
+            if let Some(variables) = included.variables.take() {
+                merge(
+                    "variable",
+                    config.variables.get_or_insert_with(IndexMap::new),
+                    variables,
+                    origins,
+                    &path,
+                )?;
+            }
+
+            if let Some(includes) = included.includes.take() {
+                include(&path, &includes, config, origins, visited)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Makes the relative `file` and `directory` paths of the templates and
+/// partials of an included file relative to the current directory instead of
+/// the included file.
+fn rebase(config: &mut Config, base: &Path) {
+    let contents = config
+        .templates
+        .values_mut()
+        .filter_map(|t| t.content.as_mut())
+        .chain(config.partials.iter_mut().flat_map(|p| p.values_mut()));
+    for content in contents {
+        if let Content::File(path) | Content::Directory(path) = content {
+            *path = base.join(&*path).display().to_string();
+        }
+    }
+}
+
+fn merge<T>(
+    kind: &'static str,
+    target: &mut impl Extend<(String, T)>,
+    source: impl IntoIterator<Item=(String, T)>,
+    origins: &mut BTreeMap<(&'static str, String), PathBuf>,
+    path: &Path,
+) -> Result<()> {
+    for (name, item) in source {
+        if let Some(origin) = origins.get(&(kind, name.to_owned())) {
+            return Err(anyhow::anyhow!(
+                "{} \"{}\" is defined in both {} and {}",
+                kind,
+                name,
+                origin.display(),
+                path.display()
+            ));
+        }
+        origins.insert((kind, name.to_owned()), path.to_owned());
+        target.extend(std::iter::once((name, item)));
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use {
+    crate::config::{
+        Config,
+        Content,
+        HelperDefinition,
+        Origin,
+        ValueType,
+    },
+    anyhow::Result,
+};
+
+#[derive(Debug)]
+pub struct ListArguments {
+    pub configuration: std::path::PathBuf,
+    pub json: bool,
+}
+
+#[derive(Debug)]
+pub struct DescribeArguments {
+    pub configuration: std::path::PathBuf,
+    pub template: String,
+    pub json: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TemplateSummary {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TemplateDescription {
+    pub name: String,
+    pub description: Option<String>,
+    pub extends: Option<String>,
+    /// The kind of content (`file`, `inline` or `directory`).
+    pub content: &'static str,
+    pub output: Option<String>,
+    pub variables: Vec<VariableDescription>,
+    pub helpers: Vec<HelperDescription>,
+    /// Whether rendering the template executes shell commands.
+    pub requires_trust: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VariableDescription {
+    pub name: String,
+    pub source: &'static str,
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    pub depends_on: Vec<String>,
+    pub requires_trust: bool,
+    /// Whether the value can be resolved by the headless backend without an
+    /// override.
+    pub headless: bool,
+    pub origin: Origin,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct HelperDescription {
+    pub name: String,
+    /// The kind of helper (`shell` or `script`).
+    pub kind: &'static str,
+    pub requires_trust: bool,
+    /// Whether the template refers to the helper. Only used helpers are
+    /// executed.
+    pub used: bool,
+    pub origin: Origin,
+}
+
+pub async fn list(invoke_options: ListArguments) -> Result<String> {
+    let cfg = crate::config::load(&invoke_options.configuration).await?;
+    let templates = cfg
+        .templates
+        .iter()
+        .map(|(name, t)| {
+            TemplateSummary {
+                name: name.to_owned(),
+                description: t.description.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if invoke_options.json {
+        return Ok(serde_json::to_string_pretty(&templates)? + "\n");
+    }
+    let width = templates.iter().map(|t| t.name.len()).max().unwrap_or(0);
+    Ok(templates
+        .iter()
+        .map(|t| {
+            match &t.description {
+                | Some(d) => format!("{:width$}  {}\n", t.name, d, width = width),
+                | None => format!("{}\n", t.name),
+            }
+        })
+        .collect())
+}
+
+pub async fn describe(invoke_options: DescribeArguments) -> Result<String> {
+    let cfg = crate::config::load(&invoke_options.configuration).await?;
+    let description = describe_template(&cfg, &invoke_options.template)?;
+
+    if invoke_options.json {
+        return Ok(serde_json::to_string_pretty(&description)? + "\n");
+    }
+    let yes_no = |v: bool| if v { "yes" } else { "no" };
+    // only inherited entries show where they come from
+    let own = Origin::Template(description.name.clone());
+    let from = |origin: &Origin| {
+        match *origin == own {
+            | true => String::new(),
+            | false => format!(" (from {})", origin),
+        }
+    };
+    let mut out = format!("name: {}\n", description.name);
+    if let Some(d) = &description.description {
+        out += &format!("description: {}\n", d);
+    }
+    if let Some(e) = &description.extends {
+        out += &format!("extends: {}\n", e);
+    }
+    out += &format!("content: {}\n", description.content);
+    if let Some(o) = &description.output {
+        out += &format!("output: {}\n", o);
+    }
+    out += &format!("requires trust: {}\n", yes_no(description.requires_trust));
+    if !description.variables.is_empty() {
+        out += "variables:\n";
+        let width = description.variables.iter().map(|v| v.name.len()).max().unwrap_or(0);
+        for v in &description.variables {
+            out += &format!(
+                "  {:width$}  {:8}  type: {:7}  trust: {:3}  headless: {}",
+                v.name,
+                v.source,
+                format!("{:?}", v.value_type).to_lowercase(),
+                yes_no(v.requires_trust),
+                yes_no(v.headless),
+                width = width
+            );
+            if !v.depends_on.is_empty() {
+                out += &format!("  depends on: {}", v.depends_on.join(", "));
+            }
+            if v.origin != own {
+                out += &format!("  from: {}", v.origin);
+            }
+            out += "\n";
+        }
+    }
+    if !description.helpers.is_empty() {
+        let helpers = description
+            .helpers
+            .iter()
+            .map(|h| format!("{}{}", h.name, from(&h.origin)))
+            .collect::<Vec<_>>();
+        out += &format!("helpers: {}\n", helpers.join(", "));
+    }
+    Ok(out)
+}
+
+fn describe_template(cfg: &Config, name: &str) -> Result<TemplateDescription> {
+    let template = cfg.resolve_template(name)?;
+    let variables = template
+        .variables
+        .iter()
+        .flatten()
+        .map(|(variable, v)| {
+            Ok(VariableDescription {
+                name: variable.to_owned(),
+                source: v.source.kind(),
+                value_type: v.attributes.value_type.unwrap_or(ValueType::String),
+                depends_on: v.attributes.depends_on.clone().unwrap_or_default(),
+                requires_trust: v.source.requires_trust(),
+                headless: v.source.resolvable_headless(),
+                origin: cfg.origin(name, "variable", variable)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let used = crate::lint::referenced_helpers(cfg, &template);
+    let mut helpers = template
+        .helpers
+        .iter()
+        .flatten()
+        .map(|(helper, definition)| {
+            Ok(HelperDescription {
+                name: helper.to_owned(),
+                kind: match definition {
+                    | HelperDefinition::Shell(..) => "shell",
+                    | HelperDefinition::Script(..) => "script",
+                },
+                requires_trust: definition.requires_trust(),
+                used: used.as_ref().map(|u| u.contains(helper)).unwrap_or(true),
+                origin: cfg.origin(name, "helper", helper)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    helpers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(TemplateDescription {
+        name: name.to_owned(),
+        description: template.description,
+        extends: cfg.templates[name].extends.clone(),
+        content: match template.content {
+            | Some(Content::File(..)) => "file",
+            | Some(Content::Inline(..)) => "inline",
+            | Some(Content::Directory(..)) => "directory",
+            | None => "none",
+        },
+        output: template.output,
+        requires_trust: helpers.iter().any(|h| h.requires_trust && h.used)
+            || variables.iter().any(|v| v.requires_trust),
+        variables,
+        helpers,
+    })
+}
@@ -1,2 +1,4 @@
 pub mod config;
+pub mod describe;
+pub mod lint;
 pub mod render;
@@ -0,0 +1,610 @@
+use {
+    crate::config::{
+        Config,
+        Content,
+        HelperDefinition,
+        Origin,
+        Template,
+        Variable,
+    },
+    anyhow::Result,
+    handlebars::template::{
+        HelperTemplate,
+        Parameter,
+        TemplateElement,
+    },
+    std::{
+        collections::{
+            BTreeMap,
+            BTreeSet,
+            HashMap,
+        },
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
+};
+
+/// The helpers that are registered in every handlebars registry.
+const BUILTIN_HELPERS: &[&str] = &[
+    "if", "unless", "each", "with", "lookup", "raw", "log", "eq", "ne", "gt", "gte", "lt", "lte", "and", "or", "not",
+    "len",
+];
+
+/// Block helpers that change the context of their body. References inside of
+/// them are relative to the new context and can not be checked.
+const SCOPING_HELPERS: &[&str] = &["each", "with"];
+
+fn is_builtin(helper: &str) -> bool {
+    BUILTIN_HELPERS.contains(&helper)
+        || crate::render::helpers::NATIVE_HELPERS.iter().any(|(n, _)| *n == helper)
+        || crate::render::escape::ESCAPE_HELPERS.iter().any(|(n, _)| *n == helper)
+}
+
+#[derive(Debug)]
+pub struct ValidateArguments {
+    pub configuration: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            | Severity::Error => "error",
+            | Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+/// Where a handlebars source comes from. Files are reported as `path:line:col`,
+/// inline sources with the line and column relative to the inline string.
+struct Source {
+    location: String,
+    file: bool,
+}
+
+impl Source {
+    fn at(&self, line: usize, column: usize) -> String {
+        if self.file {
+            format!("{}:{}:{}", self.location, line, column)
+        } else {
+            format!("{} (line {}, column {})", self.location, line, column)
+        }
+    }
+}
+
+/// Everything a handlebars source refers to, together with the locations of
+/// the references.
+#[derive(Default)]
+struct Usage {
+    variables: Vec<(String, String)>,
+    helpers: Vec<(String, String)>,
+    partials: Vec<(String, String)>,
+}
+
+impl Usage {
+    fn extend(&mut self, other: Usage) {
+        self.variables.extend(other.variables);
+        self.helpers.extend(other.helpers);
+        self.partials.extend(other.partials);
+    }
+}
+
+/// Checks the whole configuration (including all included files) and returns
+/// the problems that were found.
+pub async fn validate(invoke_options: ValidateArguments) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    let (cfg, origins) = match crate::config::load_with_sources(&invoke_options.configuration).await {
+        | Ok(x) => (x.config, x.origins),
+        | Err(e) => {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                location: invoke_options.configuration.display().to_string(),
+                message: e.to_string(),
+            });
+            return Ok(diagnostics);
+        },
+    };
+    let origin = |kind: &'static str, name: &str| {
+        origins
+            .get(&(kind, name.to_owned()))
+            .map(|p| p.display().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut partials = HashMap::<String, Usage>::new();
+    for (name, content) in cfg.partials.iter().flatten() {
+        let location = format!("{} (partial \"{}\")", origin("partial", name), name);
+        if let Content::Directory(..) = content {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                location,
+                message: "partials can not be directories".to_owned(),
+            });
+            continue;
+        }
+        if let Some(usage) = check_content(content, &location, &mut diagnostics) {
+            partials.insert(name.to_owned(), usage);
+        }
+    }
+
+    // global helpers and variables are checked once instead of for every template
+    // that inherits them
+    for (name, helper) in cfg.helpers.iter().flatten() {
+        let location = format!("{} (global)", origin("helper", name));
+        check_helper(name, helper, &location, &mut diagnostics);
+    }
+    for (name, variable) in cfg.variables.iter().flatten() {
+        let location = format!("{} (global)", origin("variable", name));
+        check_variable(name, variable, &location, &mut diagnostics);
+    }
+
+    for name in cfg.templates.keys() {
+        let location = format!("{} (template \"{}\")", origin("template", name), name);
+        let template = match cfg.resolve_template(name) {
+            | Ok(x) => x,
+            // abstract templates only exist to be extended
+            | Err(..) if is_abstract(&cfg, name) => continue,
+            | Err(e) => {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    location,
+                    message: e.to_string(),
+                });
+                continue;
+            },
+        };
+        check_template(&cfg, name, &template, &location, &partials, &mut diagnostics);
+    }
+    Ok(diagnostics)
+}
+
+fn is_abstract(cfg: &Config, name: &str) -> bool {
+    let extended = cfg.templates.values().any(|t| t.extends.as_deref() == Some(name));
+    let mut visited = Vec::<&str>::new();
+    let mut current = Some(name);
+    while let Some(n) = current {
+        if visited.contains(&n) {
+            return false;
+        }
+        visited.push(n);
+        match cfg.templates.get(n) {
+            | Some(t) if t.content.is_some() => return false,
+            | Some(t) => current = t.extends.as_deref(),
+            | None => return false,
+        }
+    }
+    extended
+}
+
+/// Returns the names of the helpers that the template (including its output
+/// path and the partials it uses) refers to. Returns `None` if the template can
+/// not be compiled.
+pub fn referenced_helpers(cfg: &Config, template: &Template) -> Option<BTreeSet<String>> {
+    let usage = usage(cfg, template)?;
+    let defined = |name: &String| template.helpers.as_ref().map(|h| h.contains_key(name)).unwrap_or(false);
+    // helpers without parameters are collected as variables
+    Some(
+        usage
+            .helpers
+            .into_iter()
+            .chain(usage.variables)
+            .map(|(name, _)| name)
+            .filter(defined)
+            .collect(),
+    )
+}
+
+/// Returns the variable references of the template (including its output path
+/// and the partials it uses), see `refers_to`. Returns `None` if the template
+/// can not be compiled.
+pub fn referenced_variables(cfg: &Config, template: &Template) -> Option<Vec<String>> {
+    Some(
+        usage(cfg, template)?
+            .variables
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+    )
+}
+
+/// Whether a reference in a template refers to the variable. Besides the
+/// variable itself, it can refer to a value nested in it or to the namespace
+/// that contains it.
+pub fn refers_to(reference: &str, variable: &str) -> bool {
+    reference == variable
+        || reference.starts_with(&format!("{}.", variable))
+        || variable.starts_with(&format!("{}.", reference))
+}
+
+fn usage(cfg: &Config, template: &Template) -> Option<Usage> {
+    let mut diagnostics = Vec::new();
+    let partials = cfg
+        .partials
+        .iter()
+        .flatten()
+        .filter_map(|(name, content)| Some((name.to_owned(), check_content(content, name, &mut diagnostics)?)))
+        .collect::<HashMap<_, _>>();
+    template_usage(cfg, template, "", &partials, &mut diagnostics)
+}
+
+/// Compiles the content and output path of the template and returns what they
+/// refer to, including the references of (transitively) used partials.
+fn template_usage(
+    cfg: &Config,
+    template: &Template,
+    location: &str,
+    partials: &HashMap<String, Usage>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Usage> {
+    let mut usage = match &template.content {
+        | Some(content) => check_content(content, &format!("{} content", location), diagnostics)?,
+        | None => Usage::default(),
+    };
+    if let Some(output) = &template.output {
+        let source = Source {
+            location: format!("{} output", location),
+            file: false,
+        };
+        usage.extend(compile(output, &source, diagnostics)?);
+    }
+
+    // the references of (transitively) used partials count as references of the
+    // template
+    let mut pending = usage.partials.clone();
+    let mut seen = BTreeSet::<String>::new();
+    while let Some((partial, at)) = pending.pop() {
+        if !seen.insert(partial.clone()) {
+            continue;
+        }
+        let in_config = cfg.partials.as_ref().map(|p| p.contains_key(&partial)).unwrap_or(false);
+        match partials.get(&partial) {
+            | Some(u) => {
+                usage.variables.extend(u.variables.iter().cloned());
+                usage.helpers.extend(u.helpers.iter().cloned());
+                pending.extend(u.partials.iter().cloned());
+            },
+            | None if in_config => {}, // already reported as invalid partial
+            | None => {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    location: at,
+                    message: format!("unknown partial \"{}\"", partial),
+                });
+            },
+        }
+    }
+    Some(usage)
+}
+
+fn check_template(
+    cfg: &Config,
+    template_name: &str,
+    template: &Template,
+    location: &str,
+    partials: &HashMap<String, Usage>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let usage = match template_usage(cfg, template, location, partials, diagnostics) {
+        | Some(x) => x,
+        | None => return,
+    };
+
+    for (helper, at) in &usage.helpers {
+        let defined = template
+            .helpers
+            .as_ref()
+            .map(|h| h.contains_key(helper))
+            .unwrap_or(false);
+        if !defined && !is_builtin(helper) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                location: at.to_owned(),
+                message: format!("unknown helper \"{}\"", helper),
+            });
+        }
+    }
+
+    let empty = Default::default();
+    let variables = template.variables.as_ref().unwrap_or(&empty);
+    for (reference, at) in &usage.variables {
+        // helpers without parameters can not be told apart from variables
+        // syntactically
+        let helper = template
+            .helpers
+            .as_ref()
+            .map(|h| h.contains_key(reference))
+            .unwrap_or(false);
+        if !helper && !variables.keys().any(|v| refers_to(reference, v)) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                location: at.to_owned(),
+                message: format!("variable \"{}\" is referenced but not defined", reference),
+            });
+        }
+    }
+
+    if let Err(e) = crate::render::resolution_order(variables, &HashMap::new()) {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            location: location.to_owned(),
+            message: e.to_string(),
+        });
+    }
+    let inherited = |kind: &str, entry: &str| matches!(cfg.origin(template_name, kind, entry), Ok(Origin::Global));
+    for (name, variable) in variables {
+        if inherited("variable", name) {
+            continue;
+        }
+        let used = usage.variables.iter().any(|(r, _)| refers_to(r, name))
+            || variables
+                .values()
+                .any(|v| v.attributes.depends_on.iter().flatten().any(|d| d == name));
+        if !used {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                location: location.to_owned(),
+                message: format!("variable \"{}\" is defined but not used", name),
+            });
+        }
+        check_variable(name, variable, location, diagnostics);
+    }
+    for (name, helper) in template
+        .helpers
+        .iter()
+        .flatten()
+        .filter(|(h, _)| !inherited("helper", h))
+        .collect::<BTreeMap<_, _>>()
+    {
+        check_helper(name, helper, location, diagnostics);
+    }
+}
+
+fn check_helper(name: &str, helper: &HelperDefinition, location: &str, diagnostics: &mut Vec<Diagnostic>) {
+    match helper {
+        | HelperDefinition::Shell(..) => {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                location: location.to_owned(),
+                message: format!("helper \"{}\" executes shell commands (requires --trust)", name),
+            });
+        },
+        | HelperDefinition::Script(script) => {
+            let engine = std::sync::Arc::new(crate::render::script::engine());
+            if let Err(e) = crate::render::script::ScriptHelper::new(name, script, engine) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    location: location.to_owned(),
+                    message: e.to_string(),
+                });
+            }
+        },
+    }
+}
+
+fn check_variable(name: &str, variable: &Variable, location: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let top_level = name.split('.').next().unwrap_or(name);
+    if is_builtin(top_level) && !BUILTIN_HELPERS.contains(&top_level) {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            location: location.to_owned(),
+            message: format!("variable \"{}\" shadows the built-in helper \"{}\"", name, top_level),
+        });
+    }
+    if let Some(regex) = variable.attributes.validation.as_ref().and_then(|v| v.regex.as_ref()) {
+        if let Err(e) = fancy_regex::Regex::new(regex) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                location: location.to_owned(),
+                message: format!("variable \"{}\" has an invalid validation regex: {}", name, e),
+            });
+        }
+    }
+    if variable.source.requires_trust() {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            location: location.to_owned(),
+            message: format!("variable \"{}\" executes shell commands (requires --trust)", name),
+        });
+    }
+}
+
+/// Compiles the content (all files of it in case of a directory) and returns
+/// what it refers to. Returns `None` if the content could not be read or
+/// compiled.
+fn check_content(content: &Content, location: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<Usage> {
+    let missing = |path: &str, diagnostics: &mut Vec<Diagnostic>| {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            location: location.to_owned(),
+            message: format!("{} does not exist", path),
+        });
+        None
+    };
+    match content {
+        | Content::Inline(x) => {
+            let source = Source {
+                location: location.to_owned(),
+                file: false,
+            };
+            compile(x, &source, diagnostics)
+        },
+        | Content::File(path) => {
+            match std::fs::read_to_string(path) {
+                | Ok(x) => {
+                    let source = Source {
+                        location: path.to_owned(),
+                        file: true,
+                    };
+                    compile(&x, &source, diagnostics)
+                },
+                | Err(..) => missing(path, diagnostics),
+            }
+        },
+        | Content::Directory(path) => {
+            if !Path::new(path).is_dir() {
+                return missing(path, diagnostics);
+            }
+            let mut usage = Usage::default();
+            let mut valid = true;
+            for file in walk(Path::new(path)) {
+                let name = Source {
+                    location: format!("{} (file name)", file.display()),
+                    file: false,
+                };
+                let relative = file.strip_prefix(path).unwrap_or(&file).to_string_lossy().to_string();
+                match compile(&relative, &name, diagnostics) {
+                    | Some(x) => usage.extend(x),
+                    | None => valid = false,
+                }
+                if file.is_dir() {
+                    continue;
+                }
+                let source = Source {
+                    location: file.display().to_string(),
+                    file: true,
+                };
+                match std::fs::read_to_string(&file)
+                    .ok()
+                    .and_then(|x| compile(&x, &source, diagnostics))
+                {
+                    | Some(x) => usage.extend(x),
+                    | None => valid = false,
+                }
+            }
+            if valid {
+                Some(usage)
+            } else {
+                None
+            }
+        },
+    }
+}
+
+/// All files and directories below the path, sorted by name.
+fn walk(path: &Path) -> Vec<PathBuf> {
+    let mut entries = std::fs::read_dir(path)
+        .map(|r| r.filter_map(|e| e.ok()).map(|e| e.path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    entries.sort();
+    let mut result = Vec::new();
+    for entry in entries {
+        let children = if entry.is_dir() { walk(&entry) } else { Vec::new() };
+        result.push(entry);
+        result.extend(children);
+    }
+    result
+}
+
+fn compile(source: &str, origin: &Source, diagnostics: &mut Vec<Diagnostic>) -> Option<Usage> {
+    match handlebars::Template::compile(source) {
+        | Ok(template) => {
+            let mut usage = Usage::default();
+            visit(&template, origin, false, &mut usage);
+            Some(usage)
+        },
+        | Err(e) => {
+            let location = match (e.line_no, e.column_no) {
+                | (Some(l), Some(c)) => origin.at(l, c),
+                | _ => origin.location.to_owned(),
+            };
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                location,
+                message: format!("invalid template: {}", e.reason()),
+            });
+            None
+        },
+    }
+}
+
+fn visit(template: &handlebars::Template, origin: &Source, scoped: bool, usage: &mut Usage) {
+    for (i, element) in template.elements.iter().enumerate() {
+        let at = template
+            .mapping
+            .get(i)
+            .map(|m| origin.at(m.0, m.1))
+            .unwrap_or_else(|| origin.location.to_owned());
+        match element {
+            | TemplateElement::Expression(h) | TemplateElement::HtmlExpression(h) => {
+                visit_helper(h, &at, origin, scoped, false, usage)
+            },
+            | TemplateElement::HelperBlock(h) => visit_helper(h, &at, origin, scoped, true, usage),
+            | TemplateElement::PartialExpression(d) | TemplateElement::PartialBlock(d) => {
+                if let Some(name) = d.name.as_name() {
+                    usage.partials.push((name.to_owned(), at.clone()));
+                }
+                for p in d.params.iter().chain(d.hash.values()) {
+                    visit_parameter(p, &at, scoped, usage);
+                }
+                if let Some(t) = &d.template {
+                    visit(t, origin, scoped, usage);
+                }
+            },
+            | _ => {},
+        }
+    }
+}
+
+fn visit_helper(h: &HelperTemplate, at: &str, origin: &Source, scoped: bool, block: bool, usage: &mut Usage) {
+    let has_params = !h.params.is_empty() || !h.hash.is_empty();
+    match &h.name {
+        | Parameter::Name(name) if block || has_params || is_builtin(name) => {
+            usage.helpers.push((name.to_owned(), at.to_owned()));
+        },
+        | name => visit_parameter(name, at, scoped, usage),
+    }
+    for p in h.params.iter().chain(h.hash.values()) {
+        visit_parameter(p, at, scoped, usage);
+    }
+
+    let scoping = h.name.as_name().map(|n| SCOPING_HELPERS.contains(&n)).unwrap_or(false);
+    if let Some(t) = &h.template {
+        visit(t, origin, scoped || scoping, usage);
+    }
+    if let Some(t) = &h.inverse {
+        visit(t, origin, scoped, usage);
+    }
+}
+
+fn visit_parameter(p: &Parameter, at: &str, scoped: bool, usage: &mut Usage) {
+    let reference = match p {
+        | Parameter::Name(n) => n.to_owned(),
+        | Parameter::Path(handlebars::Path::Relative((_, raw))) => raw.to_owned(),
+        | Parameter::Subexpression(s) => {
+            if let TemplateElement::Expression(h) = s.as_element() {
+                usage.helpers.push((s.name().to_owned(), at.to_owned()));
+                for p in h.params.iter().chain(h.hash.values()) {
+                    visit_parameter(p, at, scoped, usage);
+                }
+            }
+            return;
+        },
+        | _ => return,
+    };
+    if scoped {
+        return;
+    }
+    let reference = reference
+        .trim_start_matches("this.")
+        .trim_start_matches("./")
+        .replace('/', ".");
+    if reference.is_empty() || reference == "this" || reference.starts_with('@') || reference.starts_with("..") {
+        return;
+    }
+    usage.variables.push((reference, at.to_owned()));
+}
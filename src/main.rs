@@ -6,6 +6,8 @@ use {
 
 mod args;
 mod config;
+mod describe;
+mod lint;
 mod reference;
 mod render;
 
@@ -39,16 +41,56 @@ async fn main() -> Result<()> {
             std::fs::write("./.complate/config.yaml", config::default_config().await)?;
             Ok(())
         },
+        | args::Command::Render(x) if x.plan => {
+            let (plan, satisfiable) = render::plan(x).await?;
+            print!("{}", plan);
+            match satisfiable {
+                | true => Ok(()),
+                | false => Err(anyhow::anyhow!("headless rendering would fail")),
+            }
+        },
         | args::Command::Render(x) => {
             let res = render::select_and_render(x).await?;
             print!("{}", res);
             Ok(())
         },
+        | args::Command::Check(x) => {
+            match render::check(x).await? {
+                | Some(diff) => {
+                    print!("{}", diff);
+                    Err(anyhow::anyhow!("file is not up to date"))
+                },
+                | None => Ok(()),
+            }
+        },
+        | args::Command::List(x) => {
+            print!("{}", describe::list(x).await?);
+            Ok(())
+        },
+        | args::Command::Describe(x) => {
+            print!("{}", describe::describe(x).await?);
+            Ok(())
+        },
+        | args::Command::Validate(x) => {
+            let diagnostics = lint::validate(x).await?;
+            for d in &diagnostics {
+                println!("{}", d);
+            }
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.severity == lint::Severity::Error)
+                .count();
+            match errors {
+                | 0 => Ok(()),
+                | n => Err(anyhow::anyhow!("configuration is invalid ({} errors)", n)),
+            }
+        },
+        | args::Command::Trust(x) => {
+            print!("{}", render::trust::trust(x).await?);
+            Ok(())
+        },
         | args::Command::Direct(x) => {
-            let template = std::fs::read_to_string(x.template)?;
-            let values = std::fs::read_to_string(x.values)?;
-
-            print!("{}", render::render_direct(template, values).await?);
+            print!("{}", render::direct(x).await?);
             Ok(())
         },
         | args::Command::Schema => {
@@ -123,6 +165,16 @@ mod tests {
                     .unwrap()
                     .stdout_str()
         );
+        // a name can not be a value and a namespace at the same time
+        let err = setup_test()
+            .run("render -c $CFG -t override -v a.alpha=x -v a.alpha.b=y")
+            .unwrap()
+            .success()
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .contains("variable \"a.alpha\" is used as value and as namespace"));
     }
 
     #[test]
@@ -163,4 +215,875 @@ mod tests {
                     .stdout_str()
         );
     }
+
+    #[test]
+    fn config_includes() {
+        assert_eq!(
+            "alpha",
+            setup_test()
+                .run("render -c $CFG -t include:glob")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // file paths of included files are relative to the included file
+        assert_eq!(
+            "alpha partial",
+            setup_test()
+                .run("render -c $CFG -t include:file")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn config_includes_conflict() {
+        let err = setup_test()
+            .run("render -c ./test/.complate/conflict.yaml -t include:glob")
+            .unwrap()
+            .success()
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("template \"include:glob\" is defined in both"));
+        assert!(err.contains("conflict.yaml"));
+        assert!(err.contains("includes/alpha.yaml"));
+    }
+
+    #[test]
+    fn template_extends() {
+        assert_eq!(
+            "alpha charlie",
+            setup_test()
+                .run("render -c $CFG -t extends:child")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_extends_cycle() {
+        assert!(setup_test()
+            .run("render -c $CFG -t extends:cycle:a")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_partial() {
+        assert_eq!(
+            "hello, footer alpha",
+            setup_test()
+                .run("render -c $CFG -t partial")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        let err = setup_test()
+            .run("render -c ./test/partials/config.yaml -t plain")
+            .unwrap()
+            .success()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("partial \"tree\" can not be a directory"));
+        assert!(setup_test()
+            .run("validate -c ./test/partials/config.yaml")
+            .unwrap()
+            .stdout_str()
+            .contains("(partial \"tree\"): partials can not be directories"));
+    }
+
+    #[test]
+    fn template_var_typed() {
+        assert_eq!(
+            "disabled;alpha;bravo;42",
+            setup_test()
+                .with_env("COUNT", "42")
+                .run("render -c $CFG -t var:typed")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert_eq!(
+            "enabled;alpha;bravo;7",
+            setup_test()
+                .run("render -c $CFG -t var:typed -v a.enabled=true -v c.count=7")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_var_typed_invalid() {
+        assert!(setup_test()
+            .run("render -c $CFG -t var:typed -v c.count=seven")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_var_first_of() {
+        assert_eq!(
+            "default",
+            setup_test()
+                .run("render -c $CFG -t var:first_of")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert_eq!(
+            "alpha",
+            setup_test()
+                .with_env("ALPHA", "alpha")
+                .run("render -c $CFG -t var:first_of")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_var_depends_on() {
+        assert_eq!(
+            "zulu-alpha",
+            setup_test()
+                .run("render -c $CFG -t var:depends_on --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_var_depends_on_cycle() {
+        assert!(setup_test()
+            .run("render -c $CFG -t var:depends_on:cycle")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_var_order() {
+        let order_file = std::env::temp_dir().join("complate_test_var_order");
+        assert_eq!(
+            "zulualpha",
+            setup_test()
+                .with_env("ORDER_FILE", order_file.to_str().unwrap())
+                .run("render -c $CFG -t var:order --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_var_validation() {
+        assert_eq!(
+            "ABC-1",
+            setup_test()
+                .run("render -c $CFG -t var:validation")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // invalid candidates fall back to the next one
+        assert_eq!(
+            "ABC-1",
+            setup_test()
+                .with_env("TICKET", "bad")
+                .run("render -c $CFG -t var:validation")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert_eq!(
+            "XYZ-9",
+            setup_test()
+                .with_env("TICKET", "XYZ-9")
+                .run("render -c $CFG -t var:validation")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert!(setup_test()
+            .run("render -c $CFG -t var:validation -v a.ticket=abc")
+            .unwrap()
+            .success()
+            .is_err());
+        assert!(setup_test()
+            .run("render -c $CFG -t var:validation -v a.ticket=ABCDEFG-1")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_scaffold() {
+        let target = std::env::temp_dir().join("complate_test_scaffold");
+        let _ = std::fs::remove_dir_all(&target);
+        let cmd = format!(
+            "render -c $CFG -t scaffold -v a.name=alpha --target-dir {}",
+            target.to_str().unwrap()
+        );
+
+        setup_test().run(&cmd).unwrap().success().unwrap();
+        assert_eq!(
+            "# alpha\n",
+            std::fs::read_to_string(target.join("alpha/README.md")).unwrap()
+        );
+        assert!(target.join("alpha/src/main.rs").exists());
+
+        assert!(setup_test().run(&cmd).unwrap().success().is_err());
+        setup_test()
+            .run(&format!("{} --overwrite skip", cmd))
+            .unwrap()
+            .success()
+            .unwrap();
+
+        // the headless backend can not prompt, so nothing is written at all
+        std::fs::remove_file(target.join("alpha/README.md")).unwrap();
+        assert!(setup_test()
+            .run(&format!("{} --overwrite prompt", cmd))
+            .unwrap()
+            .success()
+            .is_err());
+        assert!(!target.join("alpha/README.md").exists());
+    }
+
+    #[test]
+    fn template_output() {
+        let out = std::env::temp_dir().join("complate_test_output");
+        let out = out.to_str().unwrap();
+        std::fs::write(out, "original").unwrap();
+
+        assert!(setup_test()
+            .run(&format!("render -c $CFG -t var:argument -o {}", out))
+            .unwrap()
+            .success()
+            .is_err());
+        assert_eq!("original", std::fs::read_to_string(out).unwrap());
+
+        setup_test()
+            .run(&format!("render -c $CFG -t var:argument -v a.alpha=alpha -o {}", out))
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!("alpha", std::fs::read_to_string(out).unwrap());
+
+        setup_test()
+            .run(&format!("render -c $CFG -t output -v a.alpha=bravo -v o.path={}", out))
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!("bravo", std::fs::read_to_string(out).unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn template_output_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        let out = std::env::temp_dir().join("complate_test_output_metadata");
+        let link = std::env::temp_dir().join("complate_test_output_metadata.link");
+        let _ = std::fs::remove_file(&link);
+        std::fs::write(&out, "original").unwrap();
+        std::fs::set_permissions(&out, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::os::unix::fs::symlink(&out, &link).unwrap();
+
+        // the symlink is written through and the mode is kept
+        setup_test()
+            .run(&format!(
+                "render -c $CFG -t var:argument -v a.alpha=alpha -o {}",
+                link.to_str().unwrap()
+            ))
+            .unwrap()
+            .success()
+            .unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!("alpha", std::fs::read_to_string(&out).unwrap());
+        assert_eq!(0o755, std::fs::metadata(&out).unwrap().permissions().mode() & 0o777);
+    }
+
+    #[test]
+    fn template_check() {
+        let file = std::env::temp_dir().join("complate_test_check");
+        let file = file.to_str().unwrap();
+        std::fs::write(file, "alpha").unwrap();
+
+        setup_test()
+            .run(&format!("check -c $CFG -t var:argument -v a.alpha=alpha -f {}", file))
+            .unwrap()
+            .success()
+            .unwrap();
+
+        assert!(setup_test()
+            .run(&format!("check -c $CFG -t var:argument -v a.alpha=bravo -f {}", file))
+            .unwrap()
+            .success()
+            .is_err());
+        assert_eq!("alpha", std::fs::read_to_string(file).unwrap());
+    }
+
+    #[test]
+    fn template_block() {
+        let out = std::env::temp_dir().join("complate_test_block");
+        let out = out.to_str().unwrap();
+        std::fs::write(
+            out,
+            "head\n<!-- complate:begin x -->\nold\n<!-- complate:end x -->\ntail\n",
+        )
+        .unwrap();
+
+        setup_test()
+            .run(&format!(
+                "render -c $CFG -t var:argument -v a.alpha=alpha -o {} --block x",
+                out
+            ))
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(
+            "head\n<!-- complate:begin x -->\nalpha\n<!-- complate:end x -->\ntail\n",
+            std::fs::read_to_string(out).unwrap()
+        );
+
+        setup_test()
+            .run(&format!(
+                "check -c $CFG -t var:argument -v a.alpha=alpha -f {} --block x",
+                out
+            ))
+            .unwrap()
+            .success()
+            .unwrap();
+
+        assert!(setup_test()
+            .run(&format!(
+                "render -c $CFG -t var:argument -v a.alpha=alpha -o {} --block y",
+                out
+            ))
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_answers() {
+        for ext in ["yaml", "json"] {
+            let answers = std::env::temp_dir().join(format!("complate_test_answers.{}", ext));
+            let answers = answers.to_str().unwrap();
+
+            assert_eq!(
+                "enabled;alpha;bravo;3",
+                setup_test()
+                    .run(&format!(
+                        "render -c $CFG -t var:typed -v a.enabled=true -v c.count=3 --record {}",
+                        answers
+                    ))
+                    .unwrap()
+                    .success()
+                    .unwrap()
+                    .stdout_str()
+            );
+            assert_eq!(
+                "enabled;alpha;bravo;3",
+                setup_test()
+                    .run(&format!("render -c $CFG -t var:typed --replay {}", answers))
+                    .unwrap()
+                    .success()
+                    .unwrap()
+                    .stdout_str()
+            );
+            assert_eq!(
+                "enabled;alpha;bravo;4",
+                setup_test()
+                    .run(&format!(
+                        "render -c $CFG -t var:typed -v c.count=4 --replay {}",
+                        answers
+                    ))
+                    .unwrap()
+                    .success()
+                    .unwrap()
+                    .stdout_str()
+            );
+        }
+
+        // nothing is recorded if the output can not be written
+        let answers = std::env::temp_dir().join("complate_test_answers_failed.yaml");
+        let out = std::env::temp_dir().join("complate_test_answers_failed.txt");
+        let _ = std::fs::remove_file(&answers);
+        std::fs::write(&out, "no markers").unwrap();
+        assert!(setup_test()
+            .run(&format!(
+                "render -c $CFG -t var:typed -v a.enabled=true -v c.count=3 --record {} -o {} --block missing",
+                answers.to_str().unwrap(),
+                out.to_str().unwrap()
+            ))
+            .unwrap()
+            .success()
+            .is_err());
+        assert!(!answers.exists());
+    }
+
+    #[test]
+    fn template_values_file() {
+        for (file, expected) in [
+            ("values.toml", "enabled;alpha;bravo;3"),
+            ("values.json", "disabled;x;5"),
+            ("values.yaml", "enabled;alpha;bravo;7"),
+        ] {
+            assert_eq!(
+                expected,
+                setup_test()
+                    .run(&format!(
+                        "render -c $CFG -t var:typed --values-file ./test/.complate/values/{}",
+                        file
+                    ))
+                    .unwrap()
+                    .success()
+                    .unwrap()
+                    .stdout_str()
+            );
+        }
+        assert_eq!(
+            "enabled;x;1",
+            setup_test()
+                .run(
+                    "render -c $CFG -t var:typed --values-file ./test/.complate/values/values.json --values-file \
+                     ./test/.complate/values/values.yaml -v c.count=1"
+                )
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // variables of type object get the whole mapping
+        assert_eq!(
+            "v/1",
+            setup_test()
+                .run("render -c $CFG -t var:object --values-file ./test/.complate/values/object.yaml")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_value_malformed() {
+        assert!(setup_test()
+            .run("render -c $CFG -t var:argument -v a.alpha")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn direct() {
+        assert_eq!(
+            "alpha delta charlie",
+            setup_test()
+                .run("direct -t ./test/direct/template.hbs -v ./test/direct/values.yaml -v ./test/direct/values.toml")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+
+        assert!(setup_test()
+            .run("direct -t ./test/direct/template.hbs -v ./test/direct/values.yaml")
+            .unwrap()
+            .success()
+            .is_err());
+        assert_eq!(
+            "alpha bravo ",
+            setup_test()
+                .run("direct -t ./test/direct/template.hbs -v ./test/direct/values.yaml --loose")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn list() {
+        let out = setup_test()
+            .run("list -c $CFG --json")
+            .unwrap()
+            .success()
+            .unwrap()
+            .stdout_str();
+        let templates = serde_json::from_str::<Vec<serde_json::Value>>(&out).unwrap();
+        assert!(templates.contains(&serde_json::json!({
+            "name": "var:typed",
+            "description": "Values of all types",
+        })));
+    }
+
+    #[test]
+    fn describe() {
+        assert_eq!(
+            indoc::indoc! {"
+                name: var:depends_on
+                content: inline
+                requires trust: yes
+                variables:
+                  a.alpha  shell     type: string   trust: yes  headless: yes  depends on: z.zulu
+                  z.zulu   static    type: string   trust: no   headless: yes
+            "},
+            setup_test()
+                .run("describe -c $CFG var:depends_on")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // arguments can only be given as overrides
+        assert_eq!(
+            indoc::indoc! {"
+                name: var:argument
+                content: inline
+                requires trust: no
+                variables:
+                  a.alpha  arg       type: string   trust: no   headless: no
+            "},
+            setup_test()
+                .run("describe -c $CFG var:argument")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert!(setup_test().run("describe -c $CFG unknown").unwrap().success().is_err());
+    }
+
+    #[test]
+    fn validate() {
+        setup_test()
+            .run("validate -c ./test/lint/valid.yaml")
+            .unwrap()
+            .success()
+            .unwrap();
+
+        let out = setup_test().run("validate -c ./test/lint/invalid.yaml").unwrap();
+        let stdout = out.stdout_str();
+        assert!(out.success().is_err());
+        for line in [
+            "error: ./test/lint/invalid.yaml (template \"file\") content: ./test/lint/missing.hbs does not exist",
+            "error: ./test/lint/invalid.yaml (template \"invalid\") content (line 2, column 1): unknown helper \
+             \"upper\"",
+            "error: ./test/lint/invalid.yaml (template \"invalid\") content (line 3, column 1): unknown partial \
+             \"missing\"",
+            "warning: ./test/lint/invalid.yaml (template \"unused\"): variable \"z.zulu\" is defined but not used",
+            "warning: ./test/lint/invalid.yaml (template \"unused\"): variable \"a.alpha\" executes shell commands \
+             (requires --trust)",
+            "warning: ./test/lint/invalid.yaml (template \"shadowed\"): variable \"date\" shadows the built-in helper \
+             \"date\"",
+        ] {
+            assert!(stdout.lines().any(|l| l == line), "missing: {}", line);
+        }
+        assert!(stdout
+            .lines()
+            .any(|l| l.starts_with("error: ./test/lint/invalid.yaml (template \"syntax\") content")));
+    }
+
+    #[test]
+    fn template_plan() {
+        assert_eq!(
+            indoc::indoc! {"
+                a.enabled  static
+                b.list     static
+                c.count    override
+            "},
+            setup_test()
+                .run("render -c $CFG -t var:typed -v c.count=3 --plan")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert!(setup_test()
+            .run("render -c $CFG -t var:depends_on --plan")
+            .unwrap()
+            .success()
+            .is_err());
+        setup_test()
+            .run("render -c $CFG -t var:depends_on --trust --plan")
+            .unwrap()
+            .success()
+            .unwrap();
+
+        // invalid overrides and untrusted shell helpers fail the render as well
+        assert!(setup_test()
+            .run("render -c $CFG -t var:typed -v c.count=seven --plan")
+            .unwrap()
+            .success()
+            .is_err());
+        let out = setup_test().run("render -c $CFG -t helper:args --plan").unwrap();
+        assert!(out.stdout_str().contains("_join  helper shell (pending trust)\n"));
+        assert!(out.success().is_err());
+        setup_test()
+            .run("render -c $CFG -t helper:args --trust --plan")
+            .unwrap()
+            .success()
+            .unwrap();
+    }
+
+    #[test]
+    fn template_trust_policy() {
+        let policy = std::env::temp_dir().join("complate_test_trust_policy.yaml");
+        let policy = policy.to_str().unwrap();
+        std::fs::write(policy, "variables: true\ncommands:\n  - printf\n  - git\n").unwrap();
+
+        // the configuration has not been approved yet
+        assert!(setup_test()
+            .run(&format!("render -c $CFG -t var:shell --trust-policy {}", policy))
+            .unwrap()
+            .success()
+            .is_err());
+
+        setup_test()
+            .run(&format!("trust -c $CFG -p {}", policy))
+            .unwrap()
+            .success()
+            .unwrap();
+        assert_eq!(
+            "alpha",
+            setup_test()
+                .run(&format!("render -c $CFG -t var:shell --trust-policy {}", policy))
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // helpers are not enabled by the policy
+        assert!(setup_test()
+            .run(&format!("render -c $CFG -t helper --trust-policy {}", policy))
+            .unwrap()
+            .success()
+            .is_err());
+        // the settings of an allowed command can not change what is executed
+        for (template, setting) in [("shell:wrapper", "shell: ./sh-wrapper"), ("shell:path", "env: PATH")] {
+            let err = setup_test()
+                .run(&format!("render -c $CFG -t {} --trust-policy {}", template, policy))
+                .unwrap()
+                .success()
+                .err()
+                .unwrap();
+            assert!(err
+                .to_string()
+                .contains(&format!("trust policy does not allow the setting {}", setting)));
+        }
+    }
+
+    #[test]
+    fn template_shell_settings() {
+        assert_eq!(
+            "hello:none:direct",
+            setup_test()
+                .run("render -c $CFG -t shell:settings --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert_eq!(
+            "bash",
+            setup_test()
+                .run("render -c $CFG -t shell:bash --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_shell_failure() {
+        let start = std::time::Instant::now();
+        assert!(setup_test()
+            .run("render -c $CFG -t shell:timeout --trust")
+            .unwrap()
+            .success()
+            .is_err());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+
+        assert!(setup_test()
+            .run("render -c $CFG -t shell:signal --trust")
+            .unwrap()
+            .success()
+            .is_err());
+    }
+
+    #[test]
+    fn template_helper_args() {
+        assert_eq!(
+            "a-b-3:3\n[\"x\",\"y\"]\n<b>test</b>",
+            setup_test()
+                .run("render -c $CFG -t helper:args --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_helper_native() {
+        assert_eq!(
+            indoc::indoc! {r#"
+                hello_world hello-world helloWorld HelloWorld HELLO WORLD
+                [x] [007] [ab  ]
+                  a
+
+                  b
+                Hello_World 1
+                dGVzdA== test 74657374 test
+                9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+                ["x","y"]
+                - x
+                - y
+
+                01.08.2023 1970-01-01T00:00:00+00:00
+                fallback Hello World NESTED"#},
+            setup_test()
+                .run("render -c $CFG -t helper:native")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // values take precedence over built-in helpers with the same name
+        assert_eq!(
+            "yesterday YESTERDAY",
+            setup_test()
+                .run("render -c $CFG -t helper:shadowed")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn template_escape() {
+        let out = setup_test()
+            .run("render -c $CFG -t escape:json")
+            .unwrap()
+            .success()
+            .unwrap()
+            .stdout_str();
+        assert_eq!(
+            r#"{"text": "say \"hi\"\nit's <me>", "raw": ["x"], "cmd": "'say \"hi\"\nit'\\''s <me>'"}"#,
+            out
+        );
+        // the shell word of escape_shell is escaped for the JSON string on top
+        let json = serde_json::from_str::<serde_json::Value>(&out).unwrap();
+        assert_eq!("'say \"hi\"\nit'\\''s <me>'", json["cmd"]);
+        assert!(setup_test()
+            .run("render -c $CFG -t escape:html")
+            .unwrap()
+            .success()
+            .unwrap()
+            .stdout_str()
+            .starts_with("{\"text\": \"say &quot;hi&quot;\nit&#x27;s &lt;me&gt;\""));
+        assert_eq!(
+            "'alpha' 'delta' 'charlie'",
+            setup_test()
+                .run(
+                    "direct -t ./test/direct/template.hbs -v ./test/direct/values.yaml -v ./test/direct/values.toml \
+                     --escape shell",
+                )
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+    }
+
+    #[test]
+    fn global_definitions() {
+        assert_eq!(
+            "COMPLATE by base (hi!)",
+            setup_test()
+                .run("render -c ./test/global/config.yaml -t readme --trust")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert_eq!(
+            indoc::indoc! {"
+                name: readme
+                extends: base
+                content: inline
+                requires trust: yes
+                variables:
+                  project  static    type: string   trust: no   headless: yes  from: global
+                  owner    static    type: string   trust: no   headless: yes  from: template base
+                helpers: _shout (from global), title
+            "},
+            setup_test()
+                .run("describe -c ./test/global/config.yaml readme")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        // inherited shell helpers only require trust and global variables are
+        // only resolved when they are used
+        assert_eq!(
+            "hi",
+            setup_test()
+                .run("render -c ./test/global/config.yaml -t plain")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert!(setup_test()
+            .run("describe -c ./test/global/config.yaml plain")
+            .unwrap()
+            .success()
+            .unwrap()
+            .stdout_str()
+            .contains("requires trust: no\n"));
+    }
+
+    #[test]
+    fn template_helper_script() {
+        // script helpers do not require trust
+        assert_eq!(
+            "x, y, z\nHELLO WORLD\n<b>world</b>",
+            setup_test()
+                .run("render -c $CFG -t helper:script")
+                .unwrap()
+                .success()
+                .unwrap()
+                .stdout_str()
+        );
+        assert!(setup_test()
+            .run("render -c $CFG -t helper:script:limits")
+            .unwrap()
+            .success()
+            .is_err());
+    }
 }
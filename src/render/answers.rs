@@ -0,0 +1,34 @@
+use {
+    anyhow::Result,
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+        path::Path,
+    },
+};
+
+/// Loads an answers file (a flat mapping of variable names to values) as value
+/// overrides. Both YAML and JSON files are accepted.
+pub fn load(path: &Path) -> Result<HashMap<String, String>> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+    let answers = serde_yaml::from_str::<BTreeMap<String, serde_json::Value>>(&content)
+        .map_err(|e| anyhow::anyhow!("invalid answers file {}: {}", path.display(), e))?;
+    Ok(answers
+        .iter()
+        .map(|(k, v)| (k.to_owned(), super::value_to_string(v)))
+        .collect())
+}
+
+/// Records the resolved values to an answers file. The file is written as JSON
+/// if it has a `.json` extension and as YAML otherwise.
+pub fn save(path: &Path, values: &HashMap<String, serde_json::Value>) -> Result<()> {
+    let answers = values.iter().collect::<BTreeMap<_, _>>();
+    let content = match path.extension().and_then(|e| e.to_str()) {
+        | Some("json") => serde_json::to_string_pretty(&answers)? + "\n",
+        | _ => serde_yaml::to_string(&answers)?,
+    };
+    super::write_atomic(path, &content)
+}
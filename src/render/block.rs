@@ -0,0 +1,64 @@
+use anyhow::Result;
+
+const BEGIN: &str = "complate:begin";
+const END: &str = "complate:end";
+
+/// Checks whether the line contains the marker for the block, e.g.
+/// `<!-- complate:begin table -->` or `# complate:end table`. Markers are
+/// matched on whitespace separated words so that they can be embedded in the
+/// comment syntax of any file type.
+fn is_marker(line: &str, marker: &str, name: &str) -> bool {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    words.windows(2).any(|w| w[0] == marker && w[1] == name)
+}
+
+/// Replaces the lines between the begin and end marker of the block with the
+/// content. The marker lines themselves and everything outside of the block
+/// are left untouched.
+pub fn replace(existing: &str, name: &str, content: &str) -> Result<String> {
+    let mut begin = Vec::<usize>::new();
+    let mut end = Vec::<usize>::new();
+    let mut offset = 0;
+    for line in existing.split_inclusive('\n') {
+        if is_marker(line, BEGIN, name) {
+            begin.push(offset + line.len());
+        }
+        if is_marker(line, END, name) {
+            end.push(offset);
+        }
+        offset += line.len();
+    }
+
+    let (begin, end) = match (begin.as_slice(), end.as_slice()) {
+        | ([], _) => {
+            return Err(anyhow::anyhow!(
+                "block \"{}\" not found (missing \"{} {}\")",
+                name,
+                BEGIN,
+                name
+            ))
+        },
+        | (_, []) => {
+            return Err(anyhow::anyhow!(
+                "block \"{}\" has no end marker (missing \"{} {}\")",
+                name,
+                END,
+                name
+            ))
+        },
+        | ([b], [e]) => (*b, *e),
+        | _ => return Err(anyhow::anyhow!("block \"{}\" is defined more than once", name)),
+    };
+    if end < begin {
+        return Err(anyhow::anyhow!("block \"{}\" ends before it begins", name));
+    }
+
+    let mut result = String::with_capacity(existing.len() + content.len());
+    result.push_str(&existing[..begin]);
+    result.push_str(content);
+    if !content.is_empty() && !content.ends_with('\n') {
+        result.push('\n');
+    }
+    result.push_str(&existing[end..]);
+    Ok(result)
+}
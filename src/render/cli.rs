@@ -20,8 +20,18 @@ impl<'a> CLIBackend<'a> {
 
 #[async_trait]
 impl<'a> UserInput for CLIBackend<'a> {
-    async fn prompt(&self, text: &str) -> Result<String> {
-        match dialoguer::Input::new().allow_empty(true).with_prompt(text).interact() {
+    async fn prompt(&self, text: &str, validation: Option<&crate::config::Validation>) -> Result<String> {
+        let input = dialoguer::Input::new()
+            .allow_empty(true)
+            .with_prompt(text)
+            .validate_with(|v: &String| {
+                match validation {
+                    | Some(validation) => validation.check(v),
+                    | None => Ok(()),
+                }
+            })
+            .interact();
+        match input {
             | Ok(res) => Ok(res),
             | Err(_) => Err(anyhow::anyhow!("interaction aborted")),
         }
@@ -38,7 +48,9 @@ impl<'a> UserInput for CLIBackend<'a> {
             .interact()?;
         match &options[&keys[result_idx]].value {
             | super::OptionValue::Static(x) => Ok(x.into()),
-            | super::OptionValue::Shell(cmd) => super::shell(cmd, &HashMap::new(), &self.shell_trust).await,
+            | super::OptionValue::Shell(cmd) => {
+                super::shell(cmd, &HashMap::new(), self.shell_trust, super::trust::ShellKind::Option).await
+            },
         }
     }
 
@@ -64,7 +76,8 @@ impl<'a> UserInput for CLIBackend<'a> {
                     let v = match &options[&keys[i]].value {
                         | super::OptionValue::Static(x) => x.into(),
                         | super::OptionValue::Shell(cmd) => {
-                            super::shell(&cmd, &HashMap::new(), &self.shell_trust).await?
+                            super::shell(cmd, &HashMap::new(), self.shell_trust, super::trust::ShellKind::Option)
+                                .await?
                         },
                     };
                     d.push_str(&v);
@@ -0,0 +1,80 @@
+use {
+    crate::config::EscapeMode,
+    handlebars::{
+        Context,
+        Handlebars,
+        Helper,
+        HelperDef,
+        RenderContext,
+        RenderError,
+        ScopedJson,
+    },
+};
+
+/// Helpers that escape a single expression for another format than the escape
+/// mode of the template, e.g. `{{ escape_shell name }}`.
+pub const ESCAPE_HELPERS: &[(&str, EscapeMode)] = &[
+    ("escape_html", EscapeMode::Html),
+    ("escape_xml", EscapeMode::Xml),
+    ("escape_json", EscapeMode::Json),
+    ("escape_yaml", EscapeMode::Yaml),
+    ("escape_shell", EscapeMode::Shell),
+];
+
+pub fn escape(mode: EscapeMode, value: &str) -> String {
+    match mode {
+        | EscapeMode::None => value.to_owned(),
+        | EscapeMode::Html => handlebars::html_escape(value),
+        | EscapeMode::Xml => {
+            value
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+                .replace('\'', "&apos;")
+        },
+        // the escape sequences of JSON strings are valid in double quoted YAML
+        // strings as well
+        | EscapeMode::Json | EscapeMode::Yaml => {
+            let quoted = serde_json::Value::String(value.to_owned()).to_string();
+            quoted[1..quoted.len() - 1].to_owned()
+        },
+        | EscapeMode::Shell => format!("'{}'", value.replace('\'', "'\\''")),
+    }
+}
+
+/// Escapes all values that are rendered with `{{ }}` according to the mode
+/// and registers the escape helpers that are not shadowed by a top-level value
+/// of the context (see `helpers::register`).
+pub fn register(hb: &mut Handlebars, mode: EscapeMode, context: &serde_json::Value) {
+    hb.register_escape_fn(move |s| escape(mode, s));
+    for (name, helper_mode) in ESCAPE_HELPERS.iter().filter(|(n, _)| context.get(n).is_none()) {
+        hb.register_helper(name, Box::new(EscapeHelper(*helper_mode, mode)));
+    }
+}
+
+/// Escapes the parameter with the mode of the helper. The result is escaped
+/// with the mode of the template (the second field) on top when it is rendered
+/// with `{{ }}`, so that e.g. a shell word inside a JSON string stays valid
+/// JSON. Escaping with the mode of the template is left to the template so
+/// that the value is not escaped twice.
+struct EscapeHelper(EscapeMode, EscapeMode);
+
+impl HelperDef for EscapeHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let value = h
+            .param(0)
+            .ok_or_else(|| RenderError::new(format!("helper \"{}\" failed: missing parameter 0", h.name())))?;
+        let value = super::value_to_string(value.value());
+        Ok(ScopedJson::Derived(serde_json::Value::String(match self.0 == self.1 {
+            | true => value,
+            | false => escape(self.0, &value),
+        })))
+    }
+}
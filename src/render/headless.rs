@@ -13,7 +13,7 @@ impl HeadlessBackend {
 
 #[async_trait]
 impl super::UserInput for HeadlessBackend {
-    async fn prompt(&self, _text: &str) -> Result<String> {
+    async fn prompt(&self, _text: &str, _validation: Option<&crate::config::Validation>) -> Result<String> {
         Err(anyhow::anyhow!("can not prompt in headless backend").into())
     }
 
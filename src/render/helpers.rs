@@ -0,0 +1,310 @@
+use {
+    crate::config::ShellCommand,
+    base64::Engine,
+    handlebars::{
+        Context,
+        Handlebars,
+        Helper,
+        HelperDef,
+        HelperResult,
+        Output,
+        RenderContext,
+        RenderError,
+        Renderable,
+        ScopedJson,
+    },
+    heck::{
+        ToKebabCase,
+        ToLowerCamelCase,
+        ToSnakeCase,
+        ToUpperCamelCase,
+    },
+    serde_json::Value,
+    sha2::Digest,
+    std::collections::HashMap,
+};
+
+type NativeFn = fn(&Helper) -> Result<Value, RenderError>;
+
+/// The helpers that are implemented natively. They do not execute any shell
+/// commands and are therefore always available without trust.
+pub const NATIVE_HELPERS: &[(&str, NativeFn)] = &[
+    ("snake_case", |h| Ok(string(h, 0)?.to_snake_case().into())),
+    ("kebab_case", |h| Ok(string(h, 0)?.to_kebab_case().into())),
+    ("camel_case", |h| Ok(string(h, 0)?.to_lower_camel_case().into())),
+    ("pascal_case", |h| Ok(string(h, 0)?.to_upper_camel_case().into())),
+    ("upper_case", |h| Ok(string(h, 0)?.to_uppercase().into())),
+    ("lower_case", |h| Ok(string(h, 0)?.to_lowercase().into())),
+    ("trim", |h| Ok(string(h, 0)?.trim().into())),
+    ("pad_start", |h| pad(h, true)),
+    ("pad_end", |h| pad(h, false)),
+    ("indent", indent),
+    ("replace", |h| {
+        Ok(string(h, 0)?.replace(&string(h, 1)?, &string(h, 2)?).into())
+    }),
+    ("regex_replace", regex_replace),
+    ("base64_encode", |h| {
+        Ok(base64::engine::general_purpose::STANDARD.encode(string(h, 0)?).into())
+    }),
+    ("base64_decode", |h| {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(string(h, 0)?)
+            .map_err(|e| error(h, e))?;
+        Ok(String::from_utf8(bytes).map_err(|e| error(h, e))?.into())
+    }),
+    ("hex_encode", |h| Ok(hex::encode(string(h, 0)?).into())),
+    ("hex_decode", |h| {
+        let bytes = hex::decode(string(h, 0)?).map_err(|e| error(h, e))?;
+        Ok(String::from_utf8(bytes).map_err(|e| error(h, e))?.into())
+    }),
+    ("sha256", |h| {
+        Ok(format!("{:x}", sha2::Sha256::digest(string(h, 0)?)).into())
+    }),
+    ("to_json", |h| {
+        let value = param(h, 0)?;
+        let json = match h.hash_get("pretty").map(|p| p.value().as_bool() == Some(true)) {
+            | Some(true) => serde_json::to_string_pretty(value),
+            | _ => serde_json::to_string(value),
+        };
+        Ok(json.map_err(|e| error(h, e))?.into())
+    }),
+    ("to_yaml", |h| {
+        Ok(serde_yaml::to_string(param(h, 0)?).map_err(|e| error(h, e))?.into())
+    }),
+    ("now", |h| {
+        let format = optional_string(h, 0).unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_owned());
+        format_date(h, chrono::Local::now().fixed_offset(), &format)
+    }),
+    ("date", date),
+    ("default", |h| {
+        match h.param(0).map(|p| p.value()).filter(|v| !is_empty(v)) {
+            | Some(v) => Ok(v.clone()),
+            | None => Ok(param(h, 1)?.clone()),
+        }
+    }),
+    ("coalesce", |h| {
+        Ok(h.params()
+            .iter()
+            .map(|p| p.value())
+            .find(|v| !is_empty(v))
+            .cloned()
+            .unwrap_or(Value::Null))
+    }),
+];
+
+/// RFC 3339 with seconds precision.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%:z";
+
+/// Registers all native helpers except the ones whose name is taken by a
+/// top-level value of the context. Handlebars prefers helpers over values in
+/// `{{ name }}`, so values like `date` would be shadowed otherwise.
+pub fn register(hb: &mut Handlebars, context: &Value) {
+    for (name, f) in NATIVE_HELPERS.iter().filter(|(n, _)| context.get(n).is_none()) {
+        hb.register_helper(name, Box::new(NativeHelper(*f)));
+    }
+}
+
+/// Wraps a native helper function. The result is returned as JSON so that the
+/// helpers can be composed with subexpressions, e.g.
+/// `{{ upper_case (default name "unknown") }}`.
+struct NativeHelper(NativeFn);
+
+impl HelperDef for NativeHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        (self.0)(h).map(ScopedJson::Derived)
+    }
+}
+
+fn error(h: &Helper, e: impl std::fmt::Display) -> RenderError {
+    RenderError::new(format!("helper \"{}\" failed: {}", h.name(), e))
+}
+
+fn param<'a>(h: &'a Helper, index: usize) -> Result<&'a Value, RenderError> {
+    h.param(index)
+        .map(|p| p.value())
+        .ok_or_else(|| error(h, format!("missing parameter {}", index)))
+}
+
+/// Returns the parameter as string. Values that are not strings are converted
+/// to their JSON representation, `null` is an empty string.
+fn string(h: &Helper, index: usize) -> Result<String, RenderError> {
+    match param(h, index)? {
+        | Value::Null => Ok(String::new()),
+        | v => Ok(super::value_to_string(v)),
+    }
+}
+
+fn optional_string(h: &Helper, index: usize) -> Option<String> {
+    h.param(index).map(|p| super::value_to_string(p.value()))
+}
+
+fn usize_param(h: &Helper, index: usize) -> Result<usize, RenderError> {
+    let value = param(h, index)?;
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        .map(|v| v as usize)
+        .ok_or_else(|| error(h, format!("parameter {} is not a positive integer", index)))
+}
+
+/// `null`, missing values and empty strings, lists and objects are empty.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        | Value::Null => true,
+        | Value::String(s) => s.is_empty(),
+        | Value::Array(a) => a.is_empty(),
+        | Value::Object(o) => o.is_empty(),
+        | _ => false,
+    }
+}
+
+/// Pads the value to the width with the fill character (default: space).
+fn pad(h: &Helper, start: bool) -> Result<Value, RenderError> {
+    let value = string(h, 0)?;
+    let width = usize_param(h, 1)?;
+    let fill = optional_string(h, 2).unwrap_or_else(|| " ".to_owned());
+    let mut fill = fill.chars();
+    let fill = match (fill.next(), fill.next()) {
+        | (Some(c), None) => c,
+        | _ => return Err(error(h, "the fill must be a single character")),
+    };
+    let padding = fill.to_string().repeat(width.saturating_sub(value.chars().count()));
+    Ok(match start {
+        | true => padding + value.as_str(),
+        | false => value + padding.as_str(),
+    }
+    .into())
+}
+
+/// Indents all lines that are not empty by the number of spaces.
+fn indent(h: &Helper) -> Result<Value, RenderError> {
+    let value = string(h, 0)?;
+    let prefix = " ".repeat(usize_param(h, 1)?);
+    Ok(value
+        .split('\n')
+        .map(|l| {
+            match l.is_empty() {
+                | true => l.to_owned(),
+                | false => format!("{}{}", prefix, l),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .into())
+}
+
+/// Replaces all matches of the regex. The replacement can reference capture
+/// groups with `$1` or `${name}`.
+fn regex_replace(h: &Helper) -> Result<Value, RenderError> {
+    let regex = fancy_regex::Regex::new(&string(h, 1)?).map_err(|e| error(h, e))?;
+    Ok(regex
+        .replace_all(&string(h, 0)?, string(h, 2)?.as_str())
+        .into_owned()
+        .into())
+}
+
+/// Formats a date which is either given as RFC 3339 string, as `YYYY-MM-DD` or
+/// as unix timestamp in seconds.
+fn date(h: &Helper) -> Result<Value, RenderError> {
+    let format = optional_string(h, 1).unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_owned());
+    let value = param(h, 0)?;
+    let date = match value {
+        | Value::Number(n) => {
+            n.as_i64()
+                .and_then(|n| chrono::DateTime::from_timestamp(n, 0))
+                .map(|d| d.fixed_offset())
+        },
+        | Value::String(s) => {
+            chrono::DateTime::parse_from_rfc3339(s).ok().or_else(|| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+                    .map(|d| d.and_utc().fixed_offset())
+            })
+        },
+        | _ => None,
+    }
+    .ok_or_else(|| error(h, format!("invalid date {}", value)))?;
+    format_date(h, date, &format)
+}
+
+fn format_date(h: &Helper, date: chrono::DateTime<chrono::FixedOffset>, format: &str) -> Result<Value, RenderError> {
+    use std::fmt::Write;
+    // formatting fails on invalid format strings which would otherwise panic
+    let mut out = String::new();
+    write!(out, "{}", date.format(format)).map_err(|_| error(h, format!("invalid date format {}", format)))?;
+    Ok(out.into())
+}
+
+/// A template helper that is implemented by a shell command. The parameters are
+/// passed as env vars:
+///
+/// - `VALUE`: the first positional parameter
+/// - `ARG0`..`ARGN`: all positional parameters, `ARGC` their count
+/// - `HASH_<name>`: the hash arguments (e.g. `HASH_sep` for `{{_join items
+///   sep=", "}}`). The prefix keeps them from overriding other env vars like
+///   `PATH`.
+/// - `BLOCK`: the rendered content of block helpers (`{{#_wrap}}..{{/_wrap}}`)
+///
+/// String values are passed as is, all other values as JSON.
+///
+/// The trust of the command is only checked when the helper is called, so that
+/// templates do not require trust for (inherited) helpers that they do not use.
+pub struct ShellHelper {
+    pub name: String,
+    pub command: ShellCommand,
+    /// Why the command may not be executed.
+    pub denied: Option<String>,
+}
+
+impl ShellHelper {
+    fn env<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<HashMap<String, String>, RenderError> {
+        let mut env = HashMap::new();
+        for (k, v) in h.hash() {
+            env.insert(format!("HASH_{}", k), super::value_to_string(v.value()));
+        }
+        if let Some(value) = h.param(0) {
+            env.insert("VALUE".to_owned(), super::value_to_string(value.value()));
+        }
+        for (i, param) in h.params().iter().enumerate() {
+            env.insert(format!("ARG{}", i), super::value_to_string(param.value()));
+        }
+        env.insert("ARGC".to_owned(), h.params().len().to_string());
+        if let Some(template) = h.template() {
+            env.insert("BLOCK".to_owned(), template.renders(r, ctx, rc)?);
+        }
+        Ok(env)
+    }
+}
+
+impl HelperDef for ShellHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        if let Some(reason) = &self.denied {
+            return Err(RenderError::new(format!("helper \"{}\" failed: {}", self.name, reason)));
+        }
+        let env = self.env(h, r, ctx, rc)?;
+        let output = super::process::run(&self.command, &env)
+            .map_err(|e| RenderError::new(format!("helper \"{}\" failed: {}", self.name, e)))?;
+        out.write(&output)?;
+        Ok(())
+    }
+}
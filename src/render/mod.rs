@@ -2,26 +2,44 @@ use {
     crate::config::{
         Config,
         Content,
+        EscapeMode,
+        HelperDefinition,
         OptionValue,
+        ShellCommand,
         Template,
+        Validation,
+        ValueType,
+        Variable,
         VariableDefinition,
     },
     anyhow::Result,
     async_trait::async_trait,
-    fancy_regex::Regex,
-    handlebars::RenderError,
+    indexmap::IndexMap,
     std::{
         collections::{
             BTreeMap,
             HashMap,
         },
         env,
+        path::{
+            Path,
+            PathBuf,
+        },
     },
 };
 
+pub mod answers;
+pub mod block;
 #[cfg(feature = "backend+cli")]
 pub mod cli;
+pub mod escape;
 pub mod headless;
+pub mod helpers;
+pub mod process;
+pub mod scaffold;
+pub mod script;
+pub mod trust;
+pub mod values;
 
 #[derive(Debug)]
 pub enum Backend {
@@ -32,143 +50,257 @@ pub enum Backend {
 
 #[derive(Debug)]
 pub struct RenderArguments {
-    pub configuration: String,
+    pub configuration: PathBuf,
     pub template: Option<String>,
-    pub value_overrides: HashMap<String, String>,
+    pub value_overrides: values::Overrides,
     pub shell_trust: ShellTrust,
     pub loose: bool,
     pub backend: Backend,
+    /// The directory into which the files of directory templates are written.
+    pub target_dir: PathBuf,
+    pub overwrite: OverwritePolicy,
+    /// The file that the rendered content is written to. Takes precedence over
+    /// the `output` of the template.
+    pub out: Option<PathBuf>,
+    /// The name of the managed block in the output file that is replaced with
+    /// the rendered content.
+    pub block: Option<String>,
+    /// The answers file that all resolved values are recorded to.
+    pub record: Option<PathBuf>,
+    /// Only prints how the variables would be resolved headlessly instead of
+    /// rendering the template.
+    pub plan: bool,
 }
 
 #[derive(Debug)]
-pub struct DirectArguments {
+pub struct Rendered {
+    pub content: String,
+    /// The rendered `output` path of the template (if defined).
+    pub output: Option<PathBuf>,
+    /// The resolved values of all variables.
+    pub values: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug)]
+pub struct CheckArguments {
+    pub configuration: PathBuf,
     pub template: String,
-    pub values: String,
+    pub value_overrides: values::Overrides,
+    pub shell_trust: ShellTrust,
+    pub loose: bool,
+    /// The file to compare the rendered content with. Defaults to the `output`
+    /// of the template.
+    pub file: Option<PathBuf>,
+    /// Only compares the managed block with this name.
+    pub block: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct DirectArguments {
+    /// The template file (`-` for `STDIN`).
+    pub template: PathBuf,
+    /// The values files (`-` for `STDIN`) that are deep merged in order.
+    pub values: Vec<PathBuf>,
+    pub loose: bool,
+    pub out: Option<PathBuf>,
+    pub block: Option<String>,
+    pub escape: EscapeMode,
+}
+
+/// Defines what happens if a file that is rendered from a directory template
+/// already exists.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OverwritePolicy {
+    Fail,
+    Skip,
+    Overwrite,
+    Prompt,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum ShellTrust {
     None,
     Ultimate,
+    /// Only the shell commands that the policy allows are executed.
+    Policy(trust::TrustPolicy),
 }
 
-pub async fn make_handlebars<'a>(
-    variable_values: &HashMap<String, String>,
-    helpers: &'a std::option::Option<HashMap<String, String>>,
-    shell_trust: &ShellTrust,
-    strict: bool,
-) -> Result<(handlebars::Handlebars<'a>, serde_json::Value)> {
-    fn recursive_add(namespace: &mut std::collections::VecDeque<String>, parent: &mut serde_json::Value, value: &str) {
+impl ShellTrust {
+    /// Checks whether the shell command may be executed.
+    pub fn check(&self, kind: trust::ShellKind, command: &ShellCommand) -> Result<()> {
+        match self {
+            | ShellTrust::None if kind == trust::ShellKind::Helper => {
+                Err(anyhow::anyhow!("need trust for executing helper functions"))
+            },
+            | ShellTrust::None => Err(anyhow::anyhow!("need trust for executing shell commands")),
+            | ShellTrust::Ultimate => Ok(()),
+            | ShellTrust::Policy(policy) => policy.allows(kind, command),
+        }
+    }
+}
+
+/// Builds the template context from the flat (dotted) variable names, e.g.
+/// `a.alpha` becomes `{ "a": { "alpha": .. } }`. Fails if a name is used as
+/// value and as namespace of another value (e.g. `a.alpha` and `a.alpha.b`).
+pub fn make_context(variable_values: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+    fn recursive_add(
+        name: &str,
+        namespace: &mut std::collections::VecDeque<String>,
+        parent: &mut serde_json::Value,
+        value: &serde_json::Value,
+    ) -> Result<()> {
+        let conflict = |key: String| anyhow::anyhow!("variable \"{}\" is used as value and as namespace", key);
+        // the parent is only a value if a shorter name was added before
+        let depth = name.split('.').count() - namespace.len();
+        let object = parent
+            .as_object_mut()
+            .ok_or_else(|| conflict(name.split('.').take(depth).collect::<Vec<_>>().join(".")))?;
         let current_namespace = namespace.pop_front().unwrap();
         match namespace.len() {
             | 0 => {
-                parent
-                    .as_object_mut()
-                    .unwrap()
-                    .entry(&current_namespace)
-                    .or_insert(serde_json::Value::String(value.into()));
+                if object.insert(current_namespace, value.clone()).is_some() {
+                    return Err(conflict(name.to_owned()));
+                }
             },
             | _ => {
-                let p = parent
-                    .as_object_mut()
-                    .unwrap()
+                let p = object
                     .entry(&current_namespace)
                     .or_insert(serde_json::Value::Object(serde_json::Map::new()));
-                recursive_add(namespace, p, value);
+                recursive_add(name, namespace, p, value)?;
             },
         }
+        Ok(())
     }
 
     let mut values_json = serde_json::Value::Object(serde_json::Map::new());
     for val in variable_values {
         let namespaces_vec: Vec<String> = val.0.split('.').map(|s| s.to_string()).collect();
         let mut namespaces = std::collections::VecDeque::from(namespaces_vec);
-        recursive_add(&mut namespaces, &mut values_json, val.1);
+        recursive_add(val.0, &mut namespaces, &mut values_json, val.1)?;
     }
+    Ok(values_json)
+}
+
+pub async fn make_handlebars<'a>(
+    variable_values: &HashMap<String, serde_json::Value>,
+    partials: &std::option::Option<BTreeMap<String, Content>>,
+    helpers: &'a std::option::Option<HashMap<String, HelperDefinition>>,
+    shell_trust: &ShellTrust,
+    escape: EscapeMode,
+    strict: bool,
+) -> Result<(handlebars::Handlebars<'a>, serde_json::Value)> {
+    let values_json = make_context(variable_values)?;
 
     let mut hb = handlebars::Handlebars::new();
-    hb.register_escape_fn(|s| s.into());
     hb.set_strict_mode(strict);
-
-    if let Some(helpers) = helpers {
-        if helpers.len() > 0 && shell_trust != &ShellTrust::Ultimate {
-            return Err(anyhow::anyhow!("need trust for executing helper functions").into());
+    escape::register(&mut hb, escape, &values_json);
+    helpers::register(&mut hb, &values_json);
+
+    if let Some(partials) = partials {
+        for (name, content) in partials {
+            let content = match content {
+                | Content::Directory(..) => {
+                    return Err(anyhow::anyhow!("partial \"{}\" can not be a directory", name));
+                },
+                | x => read_content(x).map_err(|e| anyhow::anyhow!("failed to read partial \"{}\": {}", name, e))?,
+            };
+            hb.register_partial(name, content)?;
         }
+    }
 
+    if let Some(helpers) = helpers {
+        let engine = std::sync::Arc::new(script::engine());
         for helper in helpers {
-            let h_func = move |h: &handlebars::Helper,
-                               _: &handlebars::Handlebars,
-                               _: &handlebars::Context,
-                               _: &mut handlebars::RenderContext,
-                               out: &mut dyn handlebars::Output|
-                  -> handlebars::HelperResult {
-                let param = h.param(0).ok_or(RenderError::new("parameter is not a string"))?;
-                let cmd = helper.1;
-
-                let output = std::process::Command::new("sh")
-                    .arg("-c")
-                    .arg(cmd)
-                    .env(
-                        "VALUE",
-                        param
-                            .value()
-                            .as_str()
-                            .ok_or(RenderError::new("parameter is not a string"))?,
+            match helper.1 {
+                | HelperDefinition::Shell(command) => {
+                    hb.register_helper(
+                        helper.0,
+                        Box::new(helpers::ShellHelper {
+                            name: helper.0.to_owned(),
+                            command: command.clone(),
+                            denied: shell_trust
+                                .check(trust::ShellKind::Helper, command)
+                                .err()
+                                .map(|e| e.to_string()),
+                        }),
                     )
-                    .output()?;
-                if output.status.code().unwrap() != 0 {
-                    return Err(RenderError::new("failed to get command status"));
-                }
-
-                out.write(String::from_utf8(output.stdout)?.as_str())?;
-                Ok(())
-            };
-            hb.register_helper(helper.0, Box::new(h_func))
+                },
+                | HelperDefinition::Script(source) => {
+                    hb.register_helper(
+                        helper.0,
+                        Box::new(script::ScriptHelper::new(helper.0, source, engine.clone())?),
+                    )
+                },
+            }
         }
     }
 
     Ok((hb, values_json))
 }
 
-pub async fn select_template<'a>(
-    config: &'a Config,
-    backend: &Backend,
-    shell_trust: &ShellTrust,
-) -> Result<&'a Template> {
-    let templates = config.templates.keys().cloned().collect::<Vec<String>>();
+pub async fn select_template(config: &Config, backend: &Backend, shell_trust: &ShellTrust) -> Result<String> {
     let mut template_map = BTreeMap::new();
-    for t in templates {
+    for (t, template) in &config.templates {
+        let display = match &template.description {
+            | Some(d) => format!("{} - {}", t, d),
+            | None => t.to_owned(),
+        };
         template_map.insert(t.to_owned(), crate::config::Option {
-            display: t.to_owned(),
-            value: OptionValue::Static(t.into()),
+            display,
+            value: OptionValue::Static(t.to_owned()),
         });
     }
 
     let be = backend.to_input(shell_trust)?;
     let selection = be.select("", &template_map).await?;
 
-    match config.templates.get(&selection) {
-        | Some(x) => Ok(x),
-        | None => Err(anyhow::anyhow!("invalid template selection")),
+    match config.templates.contains_key(&selection) {
+        | true => Ok(selection),
+        | false => Err(anyhow::anyhow!("invalid template selection")),
     }
 }
 
 pub async fn populate_variables(
-    vars: &std::collections::HashMap<String, VariableDefinition>,
+    vars: &IndexMap<String, Variable>,
     value_overrides: &std::collections::HashMap<String, String>,
     shell_trust: &ShellTrust,
     backend: &Backend,
     prefix: Option<String>,
-) -> Result<HashMap<String, String>> {
-    let mut values = HashMap::<String, String>::new();
+) -> Result<HashMap<String, serde_json::Value>> {
+    let mut values = HashMap::<String, serde_json::Value>::new();
     for v_override in value_overrides {
-        values.insert(v_override.0.into(), v_override.1.into());
+        let attributes = vars.get(v_override.0).map(|v| &v.attributes);
+        if let Some(validation) = attributes.and_then(|a| a.validation.as_ref()) {
+            validate(v_override.0, v_override.1, validation)?;
+        }
+        let value_type = attributes.and_then(|a| a.value_type);
+        values.insert(
+            v_override.0.into(),
+            parse_value(v_override.0, v_override.1, value_type)?,
+        );
     }
 
-    for var in vars {
-        if None == values.get(var.0) {
-            values.insert(var.0.into(), var.1.execute(shell_trust, backend).await?);
+    for name in resolution_order(vars, value_overrides)? {
+        if values.contains_key(name) {
+            continue;
+        }
+        let var = &vars[name];
+        let dependencies = var
+            .attributes
+            .depends_on
+            .iter()
+            .flatten()
+            .map(|d| (d.to_owned(), values[d].clone()))
+            .collect::<HashMap<_, _>>();
+        let validation = var.attributes.validation.as_ref();
+        let raw = var
+            .source
+            .execute(shell_trust, backend, &dependencies, validation)
+            .await?;
+        if let Some(validation) = validation {
+            validate(name, &raw, validation)?;
         }
+        values.insert(name.into(), parse_value(name, &raw, var.attributes.value_type)?);
     }
 
     let values = values
@@ -180,90 +312,497 @@ pub async fn populate_variables(
             }
             (key, v.clone())
         })
-        .collect::<HashMap<String, String>>();
+        .collect::<HashMap<String, serde_json::Value>>();
     Ok(values)
 }
 
+/// Returns the variable names in an order in which every variable comes after
+/// the variables it depends on.
+pub fn resolution_order<'a>(
+    vars: &'a IndexMap<String, Variable>,
+    value_overrides: &HashMap<String, String>,
+) -> Result<Vec<&'a String>> {
+    fn visit<'a>(
+        name: &'a String,
+        vars: &'a IndexMap<String, Variable>,
+        value_overrides: &HashMap<String, String>,
+        done: &mut HashMap<&'a String, bool>,
+        stack: &mut Vec<&'a String>,
+        order: &mut Vec<&'a String>,
+    ) -> Result<()> {
+        match done.get(name) {
+            | Some(true) => return Ok(()),
+            | Some(false) => {
+                let cycle = stack[stack.iter().position(|v| *v == name).unwrap()..]
+                    .iter()
+                    .chain(std::iter::once(&name))
+                    .map(|v| v.as_str())
+                    .collect::<Vec<_>>();
+                return Err(anyhow::anyhow!("cyclic variable dependency: {}", cycle.join(" -> ")));
+            },
+            | None => {},
+        }
+
+        done.insert(name, false);
+        stack.push(name);
+        for dependency in vars[name].attributes.depends_on.iter().flatten() {
+            match vars.get_key_value(dependency) {
+                | Some((dependency, _)) => visit(dependency, vars, value_overrides, done, stack, order)?,
+                | None if value_overrides.contains_key(dependency) => {},
+                | None => {
+                    return Err(anyhow::anyhow!(
+                        "variable \"{}\" depends on unknown variable \"{}\"",
+                        name,
+                        dependency
+                    ))
+                },
+            }
+        }
+        stack.pop();
+        done.insert(name, true);
+        order.push(name);
+        Ok(())
+    }
+
+    let mut done = HashMap::new();
+    let mut order = Vec::new();
+    for name in vars.keys() {
+        visit(name, vars, value_overrides, &mut done, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+fn validate(name: &str, value: &str, validation: &Validation) -> Result<()> {
+    validation
+        .check(value)
+        .map_err(|e| anyhow::anyhow!("invalid value for variable \"{}\": {}", name, e))
+}
+
+/// Parses the raw (string) value of a variable into the JSON representation of
+/// its declared type.
+pub fn parse_value(name: &str, raw: &str, value_type: Option<ValueType>) -> Result<serde_json::Value> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        anyhow::anyhow!(
+            "value of variable \"{}\" is not a valid {}: {}",
+            name,
+            format!("{:?}", value_type.unwrap_or(ValueType::String)).to_lowercase(),
+            e
+        )
+    };
+    Ok(match value_type.unwrap_or(ValueType::String) {
+        | ValueType::String => serde_json::Value::String(raw.into()),
+        | ValueType::Bool => serde_json::Value::Bool(raw.trim().parse::<bool>().map_err(|e| invalid(&e))?),
+        | ValueType::Integer => serde_json::Value::from(raw.trim().parse::<i64>().map_err(|e| invalid(&e))?),
+        | ValueType::Float => {
+            let v = raw.trim().parse::<f64>().map_err(|e| invalid(&e))?;
+            serde_json::Value::from(serde_json::Number::from_f64(v).ok_or_else(|| invalid(&v))?)
+        },
+        | ValueType::List => {
+            match serde_yaml::from_str::<serde_json::Value>(raw).map_err(|e| invalid(&e))? {
+                | v @ serde_json::Value::Array(..) => v,
+                | _ => return Err(invalid(&"not a sequence")),
+            }
+        },
+        | ValueType::Object => {
+            match serde_yaml::from_str::<serde_json::Value>(raw).map_err(|e| invalid(&e))? {
+                | v @ serde_json::Value::Object(..) => v,
+                | _ => return Err(invalid(&"not a mapping")),
+            }
+        },
+    })
+}
+
+/// Resolves the variables of the template and returns the handlebars registry
+/// together with the context to render the template's content with and the
+/// resolved values.
+pub async fn prepare_template<'a>(
+    template: &'a Template,
+    partials: &std::option::Option<BTreeMap<String, Content>>,
+    value_overrides: &HashMap<String, String>,
+    shell_trust: &ShellTrust,
+    backend: &Backend,
+    strict: bool,
+) -> Result<(
+    handlebars::Handlebars<'a>,
+    serde_json::Value,
+    HashMap<String, serde_json::Value>,
+)> {
+    let values = if let Some(variables) = &template.variables {
+        populate_variables(variables, value_overrides, shell_trust, backend, None).await?
+    } else {
+        HashMap::<_, _>::new()
+    };
+
+    let (hb, context) = make_handlebars(
+        &values,
+        partials,
+        &template.helpers,
+        shell_trust,
+        template.escape.unwrap_or_default(),
+        strict,
+    )
+    .await?;
+    Ok((hb, context, values))
+}
+
 pub async fn render_template(
     template: &Template,
+    partials: &std::option::Option<BTreeMap<String, Content>>,
     value_overrides: &HashMap<String, String>,
     shell_trust: &ShellTrust,
     backend: &Backend,
     strict: bool,
-) -> Result<String> {
+) -> Result<Rendered> {
     let template_str = match &template.content {
-        | Content::Inline(x) => x.into(),
-        | Content::File(x) => std::fs::read_to_string(x)?,
+        | Some(x) => read_content(x)?,
+        | None => return Err(anyhow::anyhow!("template has no content")),
     };
 
-    let values = if let Some(variables) = &template.variables {
-        populate_variables(variables, value_overrides, shell_trust, backend, None).await?
-    } else {
-        HashMap::<_, _>::new()
+    let mut hb = prepare_template(template, partials, value_overrides, shell_trust, backend, strict).await?;
+    let content =
+        hb.0.render_template(&template_str, &hb.1)
+            .map_err(|e| anyhow::anyhow!(e))?;
+    // the output path is not escaped
+    hb.0.register_escape_fn(handlebars::no_escape);
+    let output = match &template.output {
+        | Some(x) => Some(PathBuf::from(hb.0.render_template(x, &hb.1)?)),
+        | None => None,
     };
+    Ok(Rendered {
+        content,
+        output,
+        values: hb.2,
+    })
+}
 
-    let hb = make_handlebars(&values, &template.helpers, shell_trust, strict).await?;
-    hb.0.render_template(&template_str, &hb.1)
-        .map_err(|e| anyhow::anyhow!(e))
+/// Writes the content to a temporary file next to the destination and renames
+/// it afterwards so that the destination is never left in a partially written
+/// state. Symlinks are followed and the permissions of an existing file are
+/// kept.
+pub fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let path = &match path.exists() {
+        | true => std::fs::canonicalize(path)?,
+        | false => path.to_owned(),
+    };
+    let permissions = std::fs::metadata(path).ok().map(|m| m.permissions());
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("invalid output path: {}", path.display()))?;
+    let parent = match path.parent() {
+        | Some(x) if !x.as_os_str().is_empty() => x,
+        | _ => Path::new("."),
+    };
+    std::fs::create_dir_all(parent)?;
+
+    let tmp = parent.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), std::process::id()));
+    let result = std::fs::write(&tmp, content)
+        .and_then(|_| {
+            match permissions {
+                | Some(p) => std::fs::set_permissions(&tmp, p),
+                | None => Ok(()),
+            }
+        })
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(anyhow::anyhow!("failed to write {}: {}", path.display(), e));
+    }
+    Ok(())
 }
 
-pub async fn render_direct(template: String, values: String) -> Result<String> {
-    let values = serde_json::from_str::<serde_json::Value>(&values)?;
+fn read_content(content: &Content) -> Result<String> {
+    Ok(match content {
+        | Content::Inline(x) => x.into(),
+        | Content::File(x) => std::fs::read_to_string(x)?,
+        | Content::Directory(..) => return Err(anyhow::anyhow!("directory templates can only be rendered to files")),
+    })
+}
 
+pub async fn render_direct(
+    template: String,
+    values: serde_json::Value,
+    escape: EscapeMode,
+    strict: bool,
+) -> Result<String> {
     let mut hb = handlebars::Handlebars::new();
-    hb.register_escape_fn(|s| s.into());
-    hb.set_strict_mode(true);
+    hb.set_strict_mode(strict);
+    escape::register(&mut hb, escape, &values);
+    helpers::register(&mut hb, &values);
 
     Ok(hb.render_template(&template, &values)?)
 }
 
-pub async fn select_and_render(invoke_options: RenderArguments) -> Result<String> {
-    #[derive(serde::Deserialize)]
-    struct WithVersion {
-        version: String,
+/// Renders a template file with the (merged) values files without any
+/// configuration.
+pub async fn direct(invoke_options: DirectArguments) -> Result<String> {
+    let stdin = Path::new("-");
+    let stdin_count = std::iter::once(&invoke_options.template)
+        .chain(invoke_options.values.iter())
+        .filter(|p| p.as_path() == stdin)
+        .count();
+    if stdin_count > 1 {
+        return Err(anyhow::anyhow!("STDIN can only be used for one input"));
     }
-    let version_check: WithVersion = serde_yaml::from_str(&invoke_options.configuration)
-        .or::<anyhow::Error>(Err(anyhow::anyhow!("config missing version field")))?;
 
-    let version_regex = Regex::new("^([0-9]+)\\.([0-9]+)$")?;
-    if !version_regex.is_match(&version_check.version)? {
-        return Err(anyhow::anyhow!("invalid version: {}", version_check.version));
+    let template = values::read_input(&invoke_options.template)?;
+    let mut merged = serde_json::Value::Object(serde_json::Map::new());
+    for file in &invoke_options.values {
+        values::merge(&mut merged, values::load(file)?);
     }
-    let expected_version = env!("CARGO_PKG_VERSION").split(".").collect::<Vec<_>>()[..2].join(".");
-    if env!("CARGO_PKG_VERSION") != "0.0.0" {
-        if &version_check.version != &expected_version {
-            return Err(anyhow::anyhow!("config file version mismatch to binary"));
-        }
+
+    let content = render_direct(template, merged, invoke_options.escape, !invoke_options.loose).await?;
+    write_output(invoke_options.out, invoke_options.block, content)
+}
+
+/// Writes the content to the file (or only to the managed block in it) and
+/// returns an empty string. Without a file, the content is returned so that it
+/// is printed to `STDOUT`.
+fn write_output(path: Option<PathBuf>, block: Option<String>, content: String) -> Result<String> {
+    match (path, block) {
+        | (Some(path), Some(block)) => {
+            let existing = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+            write_atomic(&path, &block::replace(&existing, &block, &content)?)?;
+            Ok(String::new())
+        },
+        | (Some(path), None) => {
+            write_atomic(&path, &content)?;
+            Ok(String::new())
+        },
+        | (None, Some(..)) => Err(anyhow::anyhow!("managed blocks require an output file")),
+        | (None, None) => Ok(content),
+    }
+}
+
+/// Loads the configuration and makes sure that all of its files have been
+/// approved if a trust policy is used.
+async fn load_config(path: &Path, shell_trust: &ShellTrust) -> Result<Config> {
+    let loaded = crate::config::load_with_sources(path).await?;
+    if let ShellTrust::Policy(policy) = shell_trust {
+        policy.verify(&loaded.files)?;
     }
+    Ok(loaded.config)
+}
 
-    let cfg: Config = serde_yaml::from_str(&invoke_options.configuration)?;
+pub async fn select_and_render(invoke_options: RenderArguments) -> Result<String> {
+    let cfg = load_config(&invoke_options.configuration, &invoke_options.shell_trust).await?;
     let template = match &invoke_options.template {
-        | Some(x) => {
-            cfg.templates
-                .get(x)
-                .ok_or_else(|| anyhow::anyhow!("template not found"))?
-        },
+        | Some(x) => x.to_owned(),
         | None => select_template(&cfg, &invoke_options.backend, &invoke_options.shell_trust).await?,
     };
+    let template = cfg.resolve_template(&template)?;
+    let value_overrides = invoke_options.value_overrides.resolve(&template)?;
+
+    if let Some(Content::Directory(source)) = &template.content {
+        if invoke_options.out.is_some() || invoke_options.block.is_some() {
+            return Err(anyhow::anyhow!(
+                "directory templates can not be written to a single file (use --target-dir)"
+            ));
+        }
+        let hb = prepare_template(
+            &template,
+            &cfg.partials,
+            &value_overrides,
+            &invoke_options.shell_trust,
+            &invoke_options.backend,
+            !invoke_options.loose,
+        )
+        .await?;
+        let files = scaffold::render_tree(&hb.0, &hb.1, std::path::Path::new(source))?;
+        let input = invoke_options.backend.to_input(&invoke_options.shell_trust)?;
+        let report = scaffold::write_tree(
+            &files,
+            &invoke_options.target_dir,
+            invoke_options.overwrite,
+            input.as_ref(),
+        )
+        .await?;
+        if let Some(record) = &invoke_options.record {
+            answers::save(record, &hb.2)?;
+        }
+        return Ok(report.iter().map(|l| format!("{}\n", l)).collect());
+    }
 
-    render_template(
-        template,
-        &invoke_options.value_overrides,
+    let rendered = render_template(
+        &template,
+        &cfg.partials,
+        &value_overrides,
         &invoke_options.shell_trust,
         &invoke_options.backend,
         !invoke_options.loose,
     )
-    .await
+    .await?;
+    // the answers are only recorded once the output has been written
+    let result = write_output(
+        invoke_options.out.or(rendered.output),
+        invoke_options.block,
+        rendered.content,
+    )?;
+    if let Some(record) = &invoke_options.record {
+        answers::save(record, &rendered.values)?;
+    }
+    Ok(result)
+}
+
+/// Describes how every variable of the template would be resolved by the
+/// headless backend without executing any shell command or prompt. Returns the
+/// plan and whether headless rendering would succeed.
+pub async fn plan(invoke_options: RenderArguments) -> Result<(String, bool)> {
+    let cfg = load_config(&invoke_options.configuration, &invoke_options.shell_trust).await?;
+    let template = invoke_options
+        .template
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("template must be specified in plan mode"))?;
+    let template = cfg.resolve_template(template)?;
+    let value_overrides = invoke_options.value_overrides.resolve(&template)?;
+
+    let empty = IndexMap::new();
+    let variables = template.variables.as_ref().unwrap_or(&empty);
+    let mut steps = Vec::<(&String, (String, bool))>::new();
+    for name in resolution_order(variables, &value_overrides)? {
+        let variable = &variables[name];
+        let step = match value_overrides.get(name) {
+            // invalid overrides fail the render just like missing values
+            | Some(value) => {
+                let valid = variable
+                    .attributes
+                    .validation
+                    .as_ref()
+                    .map(|v| validate(name, value, v))
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| parse_value(name, value, variable.attributes.value_type));
+                match valid {
+                    | Ok(..) => ("override".to_owned(), true),
+                    | Err(e) => (format!("override (invalid: {})", e), false),
+                }
+            },
+            | None => plan_definition(&variable.source, &invoke_options.shell_trust),
+        };
+        steps.push((name, step));
+    }
+    // only the helpers that the template uses are executed
+    let used = crate::lint::referenced_helpers(&cfg, &template).unwrap_or_default();
+    let helpers = template.helpers.iter().flatten().collect::<BTreeMap<_, _>>();
+    for (name, helper) in helpers.into_iter().filter(|(h, _)| used.contains(*h)) {
+        let step = match helper {
+            | HelperDefinition::Shell(cmd) => {
+                let (step, ok) = plan_shell(trust::ShellKind::Helper, cmd, &invoke_options.shell_trust);
+                (format!("helper {}", step), ok)
+            },
+            | HelperDefinition::Script(..) => ("helper script".to_owned(), true),
+        };
+        steps.push((name, step));
+    }
+
+    let width = steps.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    let mut satisfiable = true;
+    for (name, (step, ok)) in steps {
+        satisfiable &= ok;
+        out += &format!("{:width$}  {}\n", name, step, width = width);
+    }
+    Ok((out, satisfiable))
+}
+
+fn plan_shell(kind: trust::ShellKind, command: &ShellCommand, shell_trust: &ShellTrust) -> (String, bool) {
+    match (shell_trust, shell_trust.check(kind, command)) {
+        | (ShellTrust::Policy(..), Ok(..)) => ("shell (allowed by trust policy)".into(), true),
+        | (ShellTrust::Policy(..), Err(..)) => ("shell (denied by trust policy)".into(), false),
+        | (_, Ok(..)) => ("shell (trusted)".into(), true),
+        | (_, Err(..)) => ("shell (pending trust)".into(), false),
+    }
+}
+
+fn plan_definition(definition: &VariableDefinition, shell_trust: &ShellTrust) -> (String, bool) {
+    match definition {
+        | VariableDefinition::Arg => ("argument (missing)".into(), false),
+        | VariableDefinition::Env(v) => {
+            match env::var(v) {
+                | Ok(..) => (format!("env \"{}\" (set)", v), true),
+                | Err(..) => (format!("env \"{}\" (not set)", v), false),
+            }
+        },
+        | VariableDefinition::Static(..) => ("static".into(), true),
+        | VariableDefinition::Shell(cmd) => plan_shell(trust::ShellKind::Variable, cmd, shell_trust),
+        | VariableDefinition::Prompt(..) | VariableDefinition::Select { .. } | VariableDefinition::Check { .. } => {
+            (format!("{} (not possible headless)", definition.kind()), false)
+        },
+        | VariableDefinition::FirstOf(definitions) => {
+            let mut steps = Vec::<String>::new();
+            for d in definitions {
+                let (step, ok) = plan_definition(d, shell_trust);
+                steps.push(step);
+                if ok {
+                    return (format!("first_of: {}", steps.join(" -> ")), true);
+                }
+            }
+            (format!("first_of: {}", steps.join(" -> ")), false)
+        },
+    }
+}
+
+/// Renders the template headlessly and compares the result with the file.
+/// Returns a unified diff if they differ.
+pub async fn check(invoke_options: CheckArguments) -> Result<Option<String>> {
+    let cfg = load_config(&invoke_options.configuration, &invoke_options.shell_trust).await?;
+    let template = cfg.resolve_template(&invoke_options.template)?;
+    if let Some(Content::Directory(..)) = &template.content {
+        return Err(anyhow::anyhow!("directory templates can not be checked"));
+    }
+
+    let rendered = render_template(
+        &template,
+        &cfg.partials,
+        &invoke_options.value_overrides.resolve(&template)?,
+        &invoke_options.shell_trust,
+        &Backend::Headless,
+        !invoke_options.loose,
+    )
+    .await?;
+    let file = invoke_options
+        .file
+        .or(rendered.output)
+        .ok_or_else(|| anyhow::anyhow!("no file to compare with (template has no output path)"))?;
+
+    let existing = match std::fs::read_to_string(&file) {
+        | Ok(x) => x,
+        | Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        | Err(e) => return Err(anyhow::anyhow!("failed to read {}: {}", file.display(), e)),
+    };
+    let expected = match &invoke_options.block {
+        | Some(block) => block::replace(&existing, block, &rendered.content)?,
+        | None => rendered.content,
+    };
+    if existing == expected {
+        return Ok(None);
+    }
+
+    let name = file.display().to_string();
+    Ok(Some(
+        similar::TextDiff::from_lines(&existing, &expected)
+            .unified_diff()
+            .header(&name, &format!("{} (rendered)", name))
+            .to_string(),
+    ))
 }
 
 #[async_trait]
 pub trait Resolve {
-    async fn execute(&self, shell_trust: &ShellTrust, backend: &Backend) -> Result<String>;
+    /// Resolves the value. `dependencies` contains the already resolved values
+    /// of the variables that were declared with `depends_on`. Interactive
+    /// backends use the `validation` to reject invalid input right away.
+    async fn execute(
+        &self,
+        shell_trust: &ShellTrust,
+        backend: &Backend,
+        dependencies: &HashMap<String, serde_json::Value>,
+        validation: Option<&Validation>,
+    ) -> Result<String>;
 }
 
 #[async_trait]
 pub trait UserInput: Send+Sync {
-    async fn prompt(&self, text: &str) -> Result<String>;
+    async fn prompt(&self, text: &str, validation: Option<&Validation>) -> Result<String>;
     async fn select(&self, prompt: &str, options: &BTreeMap<String, crate::config::Option>) -> Result<String>;
     async fn check(
         &self,
@@ -285,38 +824,89 @@ impl Backend {
 
 #[async_trait]
 impl Resolve for VariableDefinition {
-    async fn execute(&self, shell_trust: &ShellTrust, backend: &Backend) -> Result<String> {
+    async fn execute(
+        &self,
+        shell_trust: &ShellTrust,
+        backend: &Backend,
+        dependencies: &HashMap<String, serde_json::Value>,
+        validation: Option<&Validation>,
+    ) -> Result<String> {
         let backend_impl = backend.to_input(shell_trust)?;
+        // Prompt texts are templates themselves as soon as they depend on other values.
+        let text = |t: &str| -> Result<String> {
+            if dependencies.is_empty() {
+                return Ok(t.into());
+            }
+            let mut hb = handlebars::Handlebars::new();
+            hb.register_escape_fn(|s| s.into());
+            Ok(hb.render_template(t, &make_context(dependencies)?)?)
+        };
 
         match self {
             | VariableDefinition::Arg => Err(anyhow::anyhow!("variable missing")),
-            | VariableDefinition::Env(v) => Ok(env::var(v)?),
+            | VariableDefinition::Env(v) => {
+                env::var(v).map_err(|e| anyhow::anyhow!("failed to read env var \"{}\": {}", v, e))
+            },
             | VariableDefinition::Static(v) => Ok(v.into()),
-            | VariableDefinition::Prompt(v) => backend_impl.prompt(v).await,
-            | VariableDefinition::Shell(cmd) => shell(cmd, &HashMap::new(), shell_trust).await,
-            | VariableDefinition::Select { text, options } => backend_impl.select(text, options).await,
+            | VariableDefinition::Prompt(v) => backend_impl.prompt(&text(v)?, validation).await,
+            | VariableDefinition::Shell(cmd) => {
+                let env = dependencies
+                    .iter()
+                    .map(|(k, v)| (env_name(k), value_to_string(v)))
+                    .collect::<HashMap<_, _>>();
+                shell(cmd, &env, shell_trust, trust::ShellKind::Variable).await
+            },
+            | VariableDefinition::Select { text: t, options } => backend_impl.select(&text(t)?, options).await,
             | VariableDefinition::Check {
-                text,
+                text: t,
                 separator,
                 options,
-            } => backend_impl.check(text, separator, options).await,
+            } => backend_impl.check(&text(t)?, separator, options).await,
+            | VariableDefinition::FirstOf(definitions) => {
+                let mut errors = Vec::<String>::new();
+                // candidates that fail the validation fall back to the next one
+                for definition in definitions {
+                    match definition.execute(shell_trust, backend, dependencies, validation).await {
+                        | Ok(v) => {
+                            match validation.map(|x| x.check(&v)) {
+                                | Some(Err(e)) => errors.push(format!("invalid value \"{}\": {}", v, e)),
+                                | _ => return Ok(v),
+                            }
+                        },
+                        | Err(e) => errors.push(e.to_string()),
+                    }
+                }
+                Err(anyhow::anyhow!(
+                    "none of the definitions could be resolved:\n  {}",
+                    errors.join("\n  ")
+                ))
+            },
         }
     }
 }
 
-async fn shell(command: &str, env: &HashMap<String, String>, shell_trust: &ShellTrust) -> Result<String> {
-    match shell_trust {
-        | ShellTrust::None => return Err(anyhow::anyhow!("need trust for executing shell commands")),
-        | ShellTrust::Ultimate => {},
-    }
+/// Name of the env var under which a resolved variable is passed to shell
+/// commands (`a.alpha` becomes `a_alpha`).
+fn env_name(variable: &str) -> String {
+    variable
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
 
-    let output = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(command)
-        .envs(env)
-        .output()?;
-    if output.status.code().unwrap() != 0 {
-        return Err(anyhow::anyhow!("shell command error:\n{}", command));
+fn value_to_string(value: &serde_json::Value) -> String {
+    match value {
+        | serde_json::Value::String(x) => x.to_owned(),
+        | x => x.to_string(),
     }
-    Ok(String::from_utf8(output.stdout)?)
+}
+
+async fn shell(
+    command: &ShellCommand,
+    env: &HashMap<String, String>,
+    shell_trust: &ShellTrust,
+    kind: trust::ShellKind,
+) -> Result<String> {
+    shell_trust.check(kind, command)?;
+    process::run(command, env)
 }
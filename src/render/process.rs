@@ -0,0 +1,121 @@
+use {
+    crate::config::ShellCommand,
+    anyhow::Result,
+    std::{
+        collections::HashMap,
+        io::Read,
+        process::Stdio,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+/// Runs the shell command with its settings and returns its `STDOUT`. The `env`
+/// vars are passed in addition to the ones of the settings. Errors contain the
+/// `STDERR` of the command.
+pub fn run(command: &ShellCommand, env: &HashMap<String, String>) -> Result<String> {
+    let settings = &command.settings;
+    let mut process = std::process::Command::new(settings.shell.as_deref().unwrap_or("sh"));
+    process
+        .arg("-c")
+        .arg(&command.command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if settings.inherit_env == Some(false) {
+        process.env_clear();
+    }
+    process.envs(env).envs(settings.env.iter().flatten());
+    if let Some(cwd) = &settings.cwd {
+        process.current_dir(cwd);
+    }
+    // commands with a timeout run in their own process group so that the
+    // processes they spawn can be killed together with the shell
+    #[cfg(unix)]
+    if settings.timeout.is_some() {
+        use std::os::unix::process::CommandExt;
+        process.process_group(0);
+    }
+
+    let mut child = process
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("failed to execute shell command {}: {}", command.command, e))?;
+    // the pipes are drained while waiting so that the command can not block on a
+    // full pipe
+    let drain = |pipe: Option<Box<dyn Read+Send>>| {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as Box<dyn Read+Send>));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as Box<dyn Read+Send>));
+
+    let status = match settings.timeout {
+        | Some(timeout) => {
+            let deadline = Instant::now() + Duration::from_secs(timeout);
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if Instant::now() >= deadline {
+                    kill(&mut child);
+                    let _ = child.wait();
+                    return Err(anyhow::anyhow!(
+                        "shell command timed out after {}s: {}",
+                        timeout,
+                        command.command
+                    ));
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        },
+        | None => child.wait()?,
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr.join().unwrap_or_default())
+        .trim()
+        .to_owned();
+    let stderr = match stderr.is_empty() {
+        | true => stderr,
+        | false => format!("\n{}", stderr),
+    };
+
+    match status.code() {
+        | Some(0) => Ok(String::from_utf8(stdout)?),
+        | Some(code) => {
+            Err(anyhow::anyhow!(
+                "shell command failed with exit code {}: {}{}",
+                code,
+                command.command,
+                stderr
+            ))
+        },
+        | None => {
+            Err(anyhow::anyhow!(
+                "shell command was terminated by a signal: {}{}",
+                command.command,
+                stderr
+            ))
+        },
+    }
+}
+
+/// Kills the process group of the child.
+#[cfg(unix)]
+fn kill(child: &mut std::process::Child) {
+    // SAFETY: killpg has no memory safety requirements
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill(child: &mut std::process::Child) {
+    let _ = child.kill();
+}
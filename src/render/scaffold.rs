@@ -0,0 +1,141 @@
+use {
+    super::{
+        OverwritePolicy,
+        UserInput,
+    },
+    anyhow::Result,
+    std::{
+        collections::BTreeMap,
+        path::{
+            Component,
+            Path,
+            PathBuf,
+        },
+    },
+};
+
+#[derive(Debug)]
+pub struct RenderedFile {
+    /// The path relative to the target directory.
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// Renders all files below `source`. The file and directory names are templates
+/// as well.
+pub fn render_tree(
+    hb: &handlebars::Handlebars,
+    context: &serde_json::Value,
+    source: &Path,
+) -> Result<Vec<RenderedFile>> {
+    fn visit(
+        hb: &handlebars::Handlebars,
+        names: &handlebars::Handlebars,
+        context: &serde_json::Value,
+        dir: &Path,
+        relative: &Path,
+        files: &mut Vec<RenderedFile>,
+    ) -> Result<()> {
+        let mut entries = std::fs::read_dir(dir)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("invalid file name: {}", entry.path().display()))?;
+            let rendered_name = names.render_template(name, context)?;
+            let path = relative.join(&rendered_name);
+            if rendered_name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(..))) {
+                return Err(anyhow::anyhow!(
+                    "file name {} renders to invalid path \"{}\"",
+                    entry.path().display(),
+                    path.display()
+                ));
+            }
+
+            if entry.file_type()?.is_dir() {
+                visit(hb, names, context, &entry.path(), &path, files)?;
+            } else {
+                let template = std::fs::read_to_string(entry.path())
+                    .map_err(|e| anyhow::anyhow!("failed to read {}: {}", entry.path().display(), e))?;
+                let content = hb
+                    .render_template(&template, context)
+                    .map_err(|e| anyhow::anyhow!("failed to render {}: {}", entry.path().display(), e))?;
+                files.push(RenderedFile { path, content });
+            }
+        }
+        Ok(())
+    }
+
+    if !source.is_dir() {
+        return Err(anyhow::anyhow!(
+            "template directory {} does not exist",
+            source.display()
+        ));
+    }
+    // file names are not escaped
+    let mut names = hb.clone();
+    names.register_escape_fn(handlebars::no_escape);
+    let mut files = Vec::new();
+    visit(hb, &names, context, source, Path::new(""), &mut files)?;
+    Ok(files)
+}
+
+/// Writes the rendered files into the target directory and returns a line per
+/// file describing what happened.
+pub async fn write_tree(
+    files: &[RenderedFile],
+    target: &Path,
+    overwrite: OverwritePolicy,
+    input: &dyn UserInput,
+) -> Result<Vec<String>> {
+    // Nothing is written if any file would fail.
+    if overwrite == OverwritePolicy::Fail {
+        let existing = files
+            .iter()
+            .map(|f| target.join(&f.path))
+            .filter(|p| p.exists())
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+        if !existing.is_empty() {
+            return Err(anyhow::anyhow!("files already exist:\n  {}", existing.join("\n  ")));
+        }
+    }
+
+    // All prompts are answered before anything is written so that a backend
+    // that can not prompt fails without leaving a partially written tree.
+    let mut actions = Vec::new();
+    for file in files {
+        let destination = target.join(&file.path);
+        let action = match (destination.exists(), overwrite) {
+            | (false, _) => "created",
+            | (true, OverwritePolicy::Overwrite) => "overwritten",
+            | (true, OverwritePolicy::Prompt) => {
+                let mut options = BTreeMap::new();
+                for (key, display) in [("0", "overwrite"), ("1", "skip")] {
+                    options.insert(key.to_owned(), crate::config::Option {
+                        display: display.to_owned(),
+                        value: crate::config::OptionValue::Static(display.to_owned()),
+                    });
+                }
+                let prompt = format!("{} already exists", destination.display());
+                match input.select(&prompt, &options).await?.as_str() {
+                    | "overwrite" => "overwritten",
+                    | _ => "skipped",
+                }
+            },
+            | (true, _) => "skipped",
+        };
+        actions.push((file, destination, action));
+    }
+
+    let mut report = Vec::new();
+    for (file, destination, action) in actions {
+        if action != "skipped" {
+            super::write_atomic(&destination, &file.content)?;
+        }
+        report.push(format!("{} {}", action, destination.display()));
+    }
+    Ok(report)
+}
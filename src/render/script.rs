@@ -0,0 +1,103 @@
+use {
+    handlebars::{
+        Context,
+        Handlebars,
+        Helper,
+        HelperDef,
+        RenderContext,
+        RenderError,
+        Renderable,
+        ScopedJson,
+    },
+    std::sync::Arc,
+};
+
+/// Creates the engine that executes script helpers. Scripts can not import
+/// modules and `print`/`debug` are discarded, so they have no access to the
+/// filesystem or the output. The limits keep runaway scripts from hanging or
+/// exhausting the memory.
+pub fn engine() -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine
+        .set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new())
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {})
+        .set_max_operations(1_000_000)
+        .set_max_call_levels(64)
+        .set_max_expr_depths(64, 64)
+        .set_max_string_size(1 << 20)
+        .set_max_array_size(1 << 16)
+        .set_max_map_size(1 << 16);
+    engine
+}
+
+/// A template helper that is implemented by a Rhai script. The script can
+/// access the helper parameters and the render context with these variables:
+///
+/// - `params`: the positional parameters
+/// - `hash`: the hash arguments (e.g. `#{ sep: ", " }` for `{{_join items
+///   sep=", "}}`)
+/// - `context`: the values of the template
+/// - `block`: the rendered content of block helpers or `()`
+///
+/// The value of the last expression is the result of the helper.
+pub struct ScriptHelper {
+    pub name: String,
+    pub engine: Arc<rhai::Engine>,
+    pub ast: rhai::AST,
+}
+
+impl ScriptHelper {
+    pub fn new(name: &str, script: &str, engine: Arc<rhai::Engine>) -> anyhow::Result<Self> {
+        let ast = engine
+            .compile(script)
+            .map_err(|e| anyhow::anyhow!("helper \"{}\" has an invalid script: {}", name, e))?;
+        Ok(Self {
+            name: name.to_owned(),
+            engine,
+            ast,
+        })
+    }
+
+    fn error(&self, e: impl std::fmt::Display) -> RenderError {
+        RenderError::new(format!("helper \"{}\" failed: {}", self.name, e))
+    }
+
+    fn to_dynamic(&self, value: impl serde::Serialize) -> Result<rhai::Dynamic, RenderError> {
+        rhai::serde::to_dynamic(value).map_err(|e| self.error(e))
+    }
+}
+
+impl HelperDef for ScriptHelper {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'reg, 'rc>,
+        r: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        rc: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'reg, 'rc>, RenderError> {
+        let params = h.params().iter().map(|p| p.value()).collect::<Vec<_>>();
+        let hash = h
+            .hash()
+            .iter()
+            .map(|(k, v)| (*k, v.value()))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        let block = match h.template() {
+            | Some(t) => Some(t.renders(r, ctx, rc)?),
+            | None => None,
+        };
+
+        let mut scope = rhai::Scope::new();
+        scope.push_constant("params", self.to_dynamic(&params)?);
+        scope.push_constant("hash", self.to_dynamic(&hash)?);
+        scope.push_constant("context", self.to_dynamic(ctx.data())?);
+        scope.push_constant("block", self.to_dynamic(&block)?);
+
+        let result = self
+            .engine
+            .eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| self.error(e))?;
+        let value = rhai::serde::from_dynamic::<serde_json::Value>(&result).map_err(|e| self.error(e))?;
+        Ok(ScopedJson::Derived(value))
+    }
+}
@@ -0,0 +1,198 @@
+use {
+    crate::config::ShellCommand,
+    anyhow::Result,
+    sha2::Digest,
+    std::{
+        collections::BTreeMap,
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
+};
+
+/// Characters that allow a shell command to run more than the executable it
+/// starts with.
+const SHELL_OPERATORS: &[char] = &['|', '&', ';', '<', '>', '(', ')', '$', '`', '\n'];
+
+/// Where a shell command is defined in the configuration.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShellKind {
+    /// The `shell` source of a variable.
+    Variable,
+    /// A template helper.
+    Helper,
+    /// The `shell` value of a `select` or `check` option.
+    Option,
+}
+
+/// Defines which shell commands are executed instead of trusting the whole
+/// configuration. Everything that is not explicitly allowed is denied.
+#[derive(Debug, Clone, Default, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct TrustPolicy {
+    /// Allows the `shell` sources of variables.
+    #[serde(default)]
+    pub variables: bool,
+    /// Allows template helpers.
+    #[serde(default)]
+    pub helpers: bool,
+    /// Allows the `shell` values of `select` and `check` options.
+    #[serde(default)]
+    pub options: bool,
+    /// The allowed commands. An entry either matches a command exactly or
+    /// names an executable (e.g. `git`) that may be called with any arguments
+    /// as long as the command does not use shell operators like pipes.
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// The shells that may execute commands instead of the default `sh`.
+    #[serde(default)]
+    pub shells: Vec<String>,
+    /// The env vars that commands may set with their `env` setting. This
+    /// includes `PATH`, which would otherwise change what an allowed
+    /// executable resolves to.
+    #[serde(default)]
+    pub env: Vec<String>,
+    /// Allows commands that do not inherit the env vars (`inherit_env: false`).
+    #[serde(default)]
+    pub clear_env: bool,
+    /// Allows commands that run in another working directory (`cwd`).
+    #[serde(default)]
+    pub cwd: bool,
+    /// The SHA-256 hashes of the approved configuration files by their
+    /// canonical path.
+    #[serde(default)]
+    pub configs: BTreeMap<String, String>,
+}
+
+impl TrustPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read trust policy {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&content).map_err(|e| anyhow::anyhow!("invalid trust policy {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        super::write_atomic(path, &serde_yaml::to_string(self)?)
+    }
+
+    /// Checks whether the command may be executed. Besides the command itself,
+    /// its settings must not change which executable is run.
+    pub fn allows(&self, kind: ShellKind, command: &ShellCommand) -> Result<()> {
+        let settings = &command.settings;
+        let command = command.command.trim();
+        let (enabled, name) = match kind {
+            | ShellKind::Variable => (self.variables, "variables"),
+            | ShellKind::Helper => (self.helpers, "helpers"),
+            | ShellKind::Option => (self.options, "options"),
+        };
+        if !enabled {
+            return Err(anyhow::anyhow!(
+                "trust policy does not allow shell commands of {} (command: {})",
+                name,
+                command
+            ));
+        }
+
+        let executable = command.split_whitespace().next().unwrap_or_default();
+        let allowed = self
+            .commands
+            .iter()
+            .any(|c| c.trim() == command || (c.trim() == executable && !command.contains(SHELL_OPERATORS)));
+        if !allowed {
+            return Err(anyhow::anyhow!("trust policy does not allow the command: {}", command));
+        }
+
+        let denied = |setting: String| {
+            Err(anyhow::anyhow!(
+                "trust policy does not allow the setting {} (command: {})",
+                setting,
+                command
+            ))
+        };
+        if let Some(shell) = settings
+            .shell
+            .as_ref()
+            .filter(|s| *s != "sh" && !self.shells.contains(s))
+        {
+            return denied(format!("shell: {}", shell));
+        }
+        if let Some(var) = settings
+            .env
+            .iter()
+            .flatten()
+            .map(|e| e.0)
+            .find(|v| !self.env.contains(v))
+        {
+            return denied(format!("env: {}", var));
+        }
+        if settings.inherit_env == Some(false) && !self.clear_env {
+            return denied("inherit_env: false".to_owned());
+        }
+        if let Some(cwd) = settings.cwd.as_ref().filter(|_| !self.cwd) {
+            return denied(format!("cwd: {}", cwd));
+        }
+        Ok(())
+    }
+
+    /// Checks that all configuration files have been approved in their current
+    /// state.
+    pub fn verify(&self, files: &[PathBuf]) -> Result<()> {
+        for file in files {
+            let key = file.display().to_string();
+            match self.configs.get(&key) {
+                | Some(h) if *h == hash(file)? => {},
+                | Some(..) => {
+                    return Err(anyhow::anyhow!(
+                        "config file {} has changed since it was trusted (approve it again with `complate trust`)",
+                        key
+                    ))
+                },
+                | None => {
+                    return Err(anyhow::anyhow!(
+                        "config file {} is not trusted (approve it with `complate trust`)",
+                        key
+                    ))
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Records the current hashes of the configuration files.
+    pub fn approve(&mut self, files: &[PathBuf]) -> Result<()> {
+        for file in files {
+            self.configs.insert(file.display().to_string(), hash(file)?);
+        }
+        Ok(())
+    }
+}
+
+fn hash(path: &Path) -> Result<String> {
+    let content = std::fs::read(path).map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", sha2::Sha256::digest(content)))
+}
+
+#[derive(Debug)]
+pub struct TrustArguments {
+    pub configuration: PathBuf,
+    pub policy: PathBuf,
+}
+
+/// Approves the configuration (and all included files) in their current state
+/// by recording their hashes in the trust policy. The policy file is created if
+/// it does not exist.
+pub async fn trust(invoke_options: TrustArguments) -> Result<String> {
+    let loaded = crate::config::load_with_sources(&invoke_options.configuration).await?;
+    let mut policy = match invoke_options.policy.exists() {
+        | true => TrustPolicy::load(&invoke_options.policy)?,
+        | false => TrustPolicy::default(),
+    };
+    policy.approve(&loaded.files)?;
+    policy.save(&invoke_options.policy)?;
+    Ok(loaded
+        .files
+        .iter()
+        .map(|f| format!("trusted {}\n", f.display()))
+        .collect())
+}
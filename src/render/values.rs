@@ -0,0 +1,121 @@
+use {
+    anyhow::Result,
+    std::{
+        collections::HashMap,
+        io::Read,
+        path::Path,
+    },
+};
+
+/// Reads the file or `STDIN` if the path is `-`.
+pub fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|e| anyhow::anyhow!("failed to read STDIN: {}", e))?;
+        return Ok(content);
+    }
+    std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))
+}
+
+/// Loads a values file. The format is detected by the extension of the file
+/// (`.json`, `.toml`, YAML otherwise). Values read from `STDIN` (`-`) are
+/// parsed as YAML which includes JSON.
+pub fn load(path: &Path) -> Result<serde_json::Value> {
+    let content = read_input(path)?;
+    let invalid = |e: &dyn std::fmt::Display| anyhow::anyhow!("invalid values file {}: {}", path.display(), e);
+    match path.extension().and_then(|e| e.to_str()) {
+        | Some("json") => serde_json::from_str(&content).map_err(|e| invalid(&e)),
+        | Some("toml") => toml::from_str(&content).map_err(|e| invalid(&e)),
+        | _ => serde_yaml::from_str(&content).map_err(|e| invalid(&e)),
+    }
+}
+
+/// Deep merges the source into the target. Mappings are merged key by key, all
+/// other values of the source replace the ones of the target.
+pub fn merge(target: &mut serde_json::Value, source: serde_json::Value) {
+    match (target, source) {
+        | (serde_json::Value::Object(target), serde_json::Value::Object(source)) => {
+            for (k, v) in source {
+                match target.get_mut(&k) {
+                    | Some(t) => merge(t, v),
+                    | None => {
+                        target.insert(k, v);
+                    },
+                }
+            }
+        },
+        | (target, source) => *target = source,
+    }
+}
+
+/// The values that are given on the command line instead of being resolved.
+/// Later sources take precedence: answers files (`--replay`), values files
+/// (`--values-file`), single values (`-v`).
+#[derive(Debug, Default)]
+pub struct Overrides {
+    pub answers: HashMap<String, String>,
+    pub files: Vec<serde_json::Value>,
+    pub values: HashMap<String, String>,
+}
+
+impl Overrides {
+    /// Returns the overrides by variable name. The values files are flattened
+    /// up to the variables of the template, so that a variable of type `object`
+    /// is given the whole mapping.
+    pub fn resolve(&self, template: &crate::config::Template) -> Result<HashMap<String, String>> {
+        let declared = |name: &str| {
+            template
+                .variables
+                .as_ref()
+                .map(|v| v.contains_key(name))
+                .unwrap_or(false)
+        };
+        let mut overrides = self.answers.clone();
+        for values in &self.files {
+            overrides.extend(flatten(values, &declared)?);
+        }
+        overrides.extend(self.values.clone());
+        Ok(overrides)
+    }
+}
+
+/// Flattens nested mappings to the dotted variable names, e.g.
+/// `{ "a": { "alpha": "x" } }` becomes `a.alpha=x`. Mappings are not flattened
+/// any further once their name is `declared`. Sequences, scalars and the
+/// mappings of declared names are converted to their string representation.
+pub fn flatten(values: &serde_json::Value, declared: &dyn Fn(&str) -> bool) -> Result<HashMap<String, String>> {
+    fn visit(
+        prefix: Option<&str>,
+        value: &serde_json::Value,
+        declared: &dyn Fn(&str) -> bool,
+        flat: &mut HashMap<String, String>,
+    ) {
+        match (prefix, value) {
+            | (Some(p), serde_json::Value::Object(..)) if declared(p) => {
+                flat.insert(p.into(), super::value_to_string(value));
+            },
+            | (_, serde_json::Value::Object(map)) => {
+                for (k, v) in map {
+                    let key = match prefix {
+                        | Some(p) => format!("{}.{}", p, k),
+                        | None => k.to_owned(),
+                    };
+                    visit(Some(&key), v, declared, flat);
+                }
+            },
+            | (Some(p), v) => {
+                flat.insert(p.into(), super::value_to_string(v));
+            },
+            | (None, _) => {},
+        }
+    }
+
+    if !values.is_object() {
+        return Err(anyhow::anyhow!("values must be a mapping"));
+    }
+    let mut flat = HashMap::<String, String>::new();
+    visit(None, values, declared, &mut flat);
+    Ok(flat)
+}